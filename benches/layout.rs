@@ -0,0 +1,44 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use textsplit::{
+    layout::{LayoutParams, measure_text, place_glyphs},
+    parser::{alignment::parse_alignment, markup::parse_markup},
+};
+
+fn long_multiline_elements() -> Vec<textsplit::parser::markup::TextElement> {
+    let input = "テキストオブジェクトを<s48>文字別<s>に分解します\\n".repeat(256);
+    parse_markup(&input).unwrap()
+}
+
+fn params() -> LayoutParams {
+    LayoutParams {
+        x: 0.0,
+        y: 0.0,
+        size: 34.0,
+        kern: 2.0,
+        lnsp: 4.0,
+        min_size: None,
+        max_size: None,
+        line_height: None,
+    }
+}
+
+fn bench_measure(c: &mut Criterion) {
+    let elements = long_multiline_elements();
+    let params = params();
+    c.bench_function("layout/measure_text", |b| {
+        b.iter(|| measure_text(black_box(&elements), black_box(&params)))
+    });
+}
+
+fn bench_place(c: &mut Criterion) {
+    let elements = long_multiline_elements();
+    let params = params();
+    let alignment = parse_alignment("中央揃え[中]");
+    c.bench_function("layout/place_glyphs", |b| {
+        b.iter(|| place_glyphs(black_box(&elements), black_box(&params), &alignment))
+    });
+}
+
+criterion_group!(benches, bench_measure, bench_place);
+criterion_main!(benches);
@@ -0,0 +1,55 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use textsplit::parser::markup::parse_markup;
+
+fn bench_plain_text(c: &mut Criterion) {
+    let input = "テキストオブジェクトを文字別に分解します".repeat(16);
+    c.bench_function("parse_markup/plain", |b| {
+        b.iter(|| parse_markup(black_box(&input)))
+    });
+}
+
+fn bench_styled_text(c: &mut Criterion) {
+    let input = "<s40,Arial,B><#ff0000>テキスト<#>オブジェクト<s>を分解します".repeat(16);
+    c.bench_function("parse_markup/styled", |b| {
+        b.iter(|| parse_markup(black_box(&input)))
+    });
+}
+
+fn bench_small_text(c: &mut Criterion) {
+    let input = "<s40>テ<#ff0000>キ<#>スト";
+    c.bench_function("parse_markup/small", |b| {
+        b.iter(|| parse_markup(black_box(input)))
+    });
+}
+
+fn bench_large_text(c: &mut Criterion) {
+    let input = "<#00ff00>テキスト<#>オブジェクトを\\n文字別に<s*2>分解<s>します\\n".repeat(512);
+    c.bench_function("parse_markup/large", |b| {
+        b.iter(|| parse_markup(black_box(&input)))
+    });
+}
+
+fn bench_deeply_styled_text(c: &mut Criterion) {
+    // A tag before every character, which is the worst case for run splitting.
+    let input: String = "テキストオブジェクトを文字別に分解します"
+        .chars()
+        .cycle()
+        .take(1024)
+        .enumerate()
+        .map(|(i, ch)| format!("<s{},,B><#{:06x}><y{}>{}", 20 + i % 20, i * 997, i % 5, ch))
+        .collect();
+    c.bench_function("parse_markup/deeply_styled", |b| {
+        b.iter(|| parse_markup(black_box(&input)))
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_plain_text,
+    bench_styled_text,
+    bench_small_text,
+    bench_large_text,
+    bench_deeply_styled_text
+);
+criterion_main!(benches);
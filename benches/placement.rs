@@ -0,0 +1,53 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use textsplit::placement::{LayerCursor, ProbeMemory, place_incremental};
+
+/// Layers 2 to 200 are full; eight source objects on layer 1 each split
+/// into 24 glyphs over the same frames, so every block probes through the
+/// full layers and the glyphs placed before it.
+const SOURCES: usize = 8;
+const GLYPHS: usize = 24;
+
+/// Places every glyph on a mock timeline and returns the number of host
+/// calls. Without `remember`, every glyph starts probing from scratch.
+fn place_dense(remember: bool) -> usize {
+    let mut taken = vec![false; 1001];
+    taken[2..=200].fill(true);
+    let mut memory = ProbeMemory::default();
+    let mut calls = 0;
+    for _ in 0..SOURCES {
+        let mut cursor = LayerCursor::default();
+        for planned in 2..2 + GLYPHS {
+            if !remember {
+                memory = ProbeMemory::default();
+            }
+            let target = cursor.target(planned);
+            let placed = place_incremental(target, (0, 60), &mut memory, 1000, &mut |layer| {
+                calls += 1;
+                if taken[layer] {
+                    return Err("occupied");
+                }
+                taken[layer] = true;
+                Ok(())
+            })
+            .unwrap();
+            cursor.placed(target, placed);
+        }
+    }
+    calls
+}
+
+fn bench_probe_remembered(c: &mut Criterion) {
+    c.bench_function("placement/dense_remembered", |b| {
+        b.iter(|| place_dense(black_box(true)))
+    });
+}
+
+fn bench_probe_linear(c: &mut Criterion) {
+    c.bench_function("placement/dense_linear", |b| {
+        b.iter(|| place_dense(black_box(false)))
+    });
+}
+
+criterion_group!(benches, bench_probe_remembered, bench_probe_linear);
+criterion_main!(benches);
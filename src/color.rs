@@ -0,0 +1,95 @@
+/// Rotates the hue of a `rrggbb` color by `degrees`, keeping its saturation
+/// and lightness. `None` when `hex` isn't a six-digit color.
+pub fn rotate_hue(hex: &str, degrees: f32) -> Option<String> {
+    let (r, g, b) = to_rgb(hex)?;
+    let (h, s, l) = rgb_to_hsl(r, g, b);
+    let (r, g, b) = hsl_to_rgb((h + degrees).rem_euclid(360.0), s, l);
+    Some(to_hex(r, g, b))
+}
+
+/// Blends two `rrggbb` colors channel by channel, `t` going from `from` at 0
+/// to `to` at 1. `None` when either isn't a six-digit color.
+pub fn mix(from: &str, to: &str, t: f32) -> Option<String> {
+    let (r0, g0, b0) = to_rgb(from)?;
+    let (r1, g1, b1) = to_rgb(to)?;
+    let lerp = |a: f32, b: f32| a + (b - a) * t;
+    Some(to_hex(lerp(r0, r1), lerp(g0, g1), lerp(b0, b1)))
+}
+
+/// Channels of a `rrggbb` color in `0.0..=1.0`.
+fn to_rgb(hex: &str) -> Option<(f32, f32, f32)> {
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(hex.get(i..i + 2)?, 16)
+            .ok()
+            .map(|c| c as f32 / 255.0)
+    };
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+fn to_hex(r: f32, g: f32, b: f32) -> String {
+    let byte = |c: f32| (c * 255.0).round() as u8;
+    format!("{:02x}{:02x}{:02x}", byte(r), byte(g), byte(b))
+}
+
+fn rgb_to_hsl(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return (0.0, 0.0, l);
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / d + 2.0)
+    } else {
+        60.0 * ((r - g) / d + 4.0)
+    };
+    (h, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    (r + m, g + m, b + m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rotate_hue_half_turn() {
+        assert_eq!(rotate_hue("ff0000", 180.0).as_deref(), Some("00ffff"));
+        assert_eq!(rotate_hue("ff0000", 120.0).as_deref(), Some("00ff00"));
+        assert_eq!(rotate_hue("ff0000", -120.0).as_deref(), Some("0000ff"));
+    }
+
+    #[test]
+    fn test_mix_blends_channels() {
+        assert_eq!(mix("ff0000", "0000ff", 0.0).as_deref(), Some("ff0000"));
+        assert_eq!(mix("ff0000", "0000ff", 0.5).as_deref(), Some("800080"));
+        assert_eq!(mix("ff0000", "0000ff", 1.0).as_deref(), Some("0000ff"));
+        assert_eq!(mix("ff0000", "xyz", 0.5), None);
+    }
+
+    #[test]
+    fn test_rotate_hue_keeps_grays_and_rejects_short_colors() {
+        assert_eq!(rotate_hue("808080", 90.0).as_deref(), Some("808080"));
+        assert_eq!(rotate_hue("123", 90.0), None);
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{
+    params::TEXT_EFFECT,
+    parser::markup::{ParseOptions, UnknownTagPolicy},
+    placement::MAX_CONSECUTIVE_FAILURES,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, path::PathBuf, sync::OnceLock};
+
+static CONFIG: OnceLock<SplitConfig> = OnceLock::new();
+
+const CONFIG_FILE_NAME: &str = "textsplit.json";
+const EXPORT_FILE_NAME: &str = "textsplit.object";
+
+/// Which point of a glyph object its X/Y refers to.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum GlyphOrigin {
+    #[default]
+    TopLeft,
+    Center,
+}
+
+/// How glyph objects are assigned to layers.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum LayerPolicy {
+    /// One layer per glyph, bumped upwards past occupied layers and failing
+    /// once none is left.
+    #[default]
+    Spread,
+    /// Glyphs whose ranges don't overlap share a layer, so many short
+    /// objects need far fewer layers.
+    Compact,
+}
+
+/// Kinds of characters `skip_classes` can leave out of the split.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CharClass {
+    Whitespace,
+    /// ASCII punctuation plus the CJK and full-width punctuation marks.
+    Punctuation,
+    Digit,
+}
+
+impl CharClass {
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            CharClass::Whitespace => c.is_whitespace(),
+            CharClass::Punctuation => {
+                c.is_ascii_punctuation()
+                    || matches!(c,
+                        '\u{3001}'..='\u{303f}'
+                        | '\u{ff01}'..='\u{ff0f}'
+                        | '\u{ff1a}'..='\u{ff20}'
+                        | '\u{ff3b}'..='\u{ff40}'
+                        | '\u{ff5b}'..='\u{ff65}')
+            }
+            CharClass::Digit => c.is_numeric(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SplitConfig {
+    /// Font name used when neither the markup nor the object specifies one.
+    pub fallback_font: Option<String>,
+    /// Frames between consecutive glyphs in the typewriter split.
+    pub typewriter_step: usize,
+    /// Extra string treated as a line break in addition to real newlines.
+    pub newline_marker: Option<String>,
+    /// Outline width applied to runs without an `<ow>` tag; `None` adds no
+    /// outline filter.
+    pub outline_width: Option<f32>,
+    /// Normalize the text to NFC before splitting.
+    pub normalize_nfc: bool,
+    /// Advance past whitespace without creating objects for it.
+    pub skip_spaces: bool,
+    /// Layer that sources kept by the reference split are moved to; `None`
+    /// leaves them on their own layer, below the glyphs.
+    pub reference_layer: Option<usize>,
+    /// Warn about unexpected characters in markup flags.
+    pub strict_markup: bool,
+    /// Multiplies every size, kern, lnsp and position, e.g. when reusing
+    /// markup written for another canvas resolution. The host reports no
+    /// display scale, and its preview zoom doesn't change object values, so
+    /// this is the only scale applied.
+    pub scale_factor: f32,
+    /// Effect names the text is read from, tried in order.
+    pub text_effects: Vec<String>,
+    /// Font files by font name, used for real advance widths when built
+    /// with the `font-metrics` feature.
+    pub font_files: HashMap<String, String>,
+    /// Offset for glyphs past the end of a `<timing>` list; `None` holds the
+    /// list's last value.
+    pub timing_default: Option<usize>,
+    /// Anchor of the generated glyph objects.
+    pub glyph_origin: GlyphOrigin,
+    /// Where the alias export is written; next to the host executable when
+    /// unset.
+    pub export_path: Option<String>,
+    /// Smallest glyph size; smaller sizes from the markup are raised to it.
+    pub min_size: Option<f32>,
+    /// Largest glyph size; larger sizes from the markup are lowered to it.
+    pub max_size: Option<f32>,
+    /// How `<...>` fragments that aren't tags are treated.
+    pub unknown_tags: UnknownTagPolicy,
+    /// Emit glyphs as path figures traced from the font in `font_files`
+    /// when built with the `glyph-paths` feature.
+    pub glyph_paths: bool,
+    /// Make `<#>` restore the color before the last `<#…>` instead of the
+    /// object's color.
+    pub color_stack: bool,
+    /// Project resolution that `canvas_offset` is relative to.
+    pub canvas_size: (u32, u32),
+    /// Shift of every glyph in percent of `canvas_size`, so the same markup
+    /// adapts to other resolutions.
+    pub canvas_offset: (f32, f32),
+    /// Advance lines by this multiple of their tallest glyph instead of by
+    /// the size plus the object's 行間.
+    pub line_height: Option<f32>,
+    /// Trim whitespace at both ends of every line before layout.
+    pub trim_lines: bool,
+    /// Objects every line is cut into by the equal-parts split.
+    pub chunk_count: usize,
+    /// Glyphs in each object of the fixed-size split.
+    pub chunk_size: usize,
+    /// Letters accepted in style tag flags in place of `B` and `I`.
+    pub flag_aliases: HashMap<char, char>,
+    /// How glyphs are spread over the layers above the source.
+    pub layer_policy: LayerPolicy,
+    /// Width that lines with `<sauto>` are fitted to, in canvas pixels;
+    /// `<sauto>` is the base size when unset.
+    pub fit_width: Option<f32>,
+    /// Advance every glyph by this width in canvas pixels, whatever its
+    /// size, for monospaced output.
+    pub monospace_width: Option<f32>,
+    /// Split the object's name when none of `text_effects` has text.
+    pub name_fallback: bool,
+    /// Place every line's glyphs in reverse, last glyph first, on the same
+    /// positions.
+    pub reverse_order: bool,
+    /// Characters that get no object of their own but still take up their
+    /// width.
+    pub skip_chars: String,
+    /// Like `skip_chars`, for whole kinds of characters.
+    pub skip_classes: Vec<CharClass>,
+    /// Only selected objects whose name contains this are split.
+    pub name_filter: Option<String>,
+    /// How much larger the stroke copies of the stroke split are, in canvas
+    /// pixels.
+    pub stroke_size_delta: f32,
+    /// Color of the stroke copies; the object's 影・縁色 when unset.
+    pub stroke_color: Option<String>,
+    /// Stack the glyphs of all selected objects above the highest of them,
+    /// one object's block after another, instead of above each source.
+    pub above_selection: bool,
+    /// 字間 and 行間 values in this range are ratios of the size, as some
+    /// presets store them, rather than pixels.
+    pub spacing_ratio_range: Option<(f32, f32)>,
+    /// 字間 the re-layout menu spaces glyphs by; the glyphs' own when unset.
+    pub relayout_kern: Option<f32>,
+    /// 行間 the re-layout menu spaces lines by; the current spacing when
+    /// unset.
+    pub relayout_lnsp: Option<f32>,
+    /// Replace markup fonts that have no file in `font_files` by
+    /// `fallback_font`, when built with the `font-metrics` feature.
+    pub replace_missing_fonts: bool,
+    /// Failed creations in a row on the layers above a glyph's planned one
+    /// after which the split gives up; 0 counts as 1.
+    pub max_probe_attempts: usize,
+}
+
+impl Default for SplitConfig {
+    fn default() -> Self {
+        SplitConfig {
+            fallback_font: None,
+            typewriter_step: 1,
+            newline_marker: None,
+            outline_width: None,
+            normalize_nfc: false,
+            skip_spaces: false,
+            reference_layer: None,
+            strict_markup: false,
+            scale_factor: 1.0,
+            text_effects: vec![TEXT_EFFECT.to_string()],
+            font_files: HashMap::new(),
+            timing_default: None,
+            glyph_origin: GlyphOrigin::TopLeft,
+            export_path: None,
+            min_size: None,
+            max_size: None,
+            unknown_tags: UnknownTagPolicy::Keep,
+            glyph_paths: false,
+            color_stack: false,
+            canvas_size: (1920, 1080),
+            canvas_offset: (0.0, 0.0),
+            line_height: None,
+            trim_lines: false,
+            chunk_count: 5,
+            chunk_size: 2,
+            flag_aliases: HashMap::new(),
+            layer_policy: LayerPolicy::Spread,
+            fit_width: None,
+            monospace_width: None,
+            name_fallback: false,
+            reverse_order: false,
+            skip_chars: String::new(),
+            skip_classes: Vec::new(),
+            name_filter: None,
+            stroke_size_delta: 6.0,
+            stroke_color: None,
+            above_selection: false,
+            spacing_ratio_range: None,
+            relayout_kern: None,
+            relayout_lnsp: None,
+            replace_missing_fonts: false,
+            max_probe_attempts: MAX_CONSECUTIVE_FAILURES,
+        }
+    }
+}
+
+impl SplitConfig {
+    /// Reads `textsplit.json` next to the host executable, falling back to the
+    /// defaults when the file is missing or malformed.
+    pub fn load() -> SplitConfig {
+        let Some(path) = std::env::current_exe()
+            .ok()
+            .map(|exe| exe.with_file_name(CONFIG_FILE_NAME))
+        else {
+            return SplitConfig::default();
+        };
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            return SplitConfig::default();
+        };
+        match serde_json::from_str(&content) {
+            Ok(config) => config,
+            Err(e) => {
+                aviutl2::log::warn!("{} の読み込みに失敗しました: {}", path.display(), e);
+                SplitConfig::default()
+            }
+        }
+    }
+
+    pub fn export_path(&self) -> PathBuf {
+        match &self.export_path {
+            Some(path) => PathBuf::from(path),
+            None => std::env::current_exe()
+                .map(|exe| exe.with_file_name(EXPORT_FILE_NAME))
+                .unwrap_or_else(|_| PathBuf::from(EXPORT_FILE_NAME)),
+        }
+    }
+
+    /// Whether a glyph object holding `text` is left out, because all of it
+    /// is whitespace under `skip_spaces` or matches `skip_chars` or
+    /// `skip_classes`.
+    pub fn skips_glyph(&self, text: &str) -> bool {
+        text.chars().all(|c| {
+            (self.skip_spaces && c.is_whitespace())
+                || self.skip_chars.contains(c)
+                || self.skip_classes.iter().any(|class| class.contains(c))
+        })
+    }
+
+    /// Whether an object named `name` is split under `name_filter`.
+    pub fn matches_name(&self, name: Option<&str>) -> bool {
+        match &self.name_filter {
+            Some(filter) => name.is_some_and(|name| name.contains(filter.as_str())),
+            None => true,
+        }
+    }
+
+    /// `canvas_offset` in pixels.
+    pub fn canvas_offset_px(&self) -> (f32, f32) {
+        let (width, height) = self.canvas_size;
+        let (x, y) = self.canvas_offset;
+        (width as f32 * x / 100.0, height as f32 * y / 100.0)
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            newline_marker: self.newline_marker.clone(),
+            normalize_nfc: self.normalize_nfc,
+            strict: self.strict_markup,
+            unknown_tags: self.unknown_tags,
+            color_stack: self.color_stack,
+            flag_aliases: self.flag_aliases.clone(),
+        }
+    }
+}
+
+pub fn init(config: SplitConfig) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static SplitConfig {
+    CONFIG.get_or_init(SplitConfig::default)
+}
@@ -1,16 +1,25 @@
+/// Line written into every generated glyph's `[Object]` section so that
+/// already-split objects can be recognized later.
+pub const GLYPH_MARKER: &str = "textsplit=glyph";
+
+/// Longest alias in bytes handed to the host. Longer ones are rejected up
+/// front, since the host fails on them without saying why.
+pub const MAX_ALIAS_BYTES: usize = 64 * 1024;
+
 pub const TEXT_ALIAS_TEMPLATE: &'static str = "[Object]
 frame={start},{end}
+textsplit=glyph
 [Object.0]
 effect.name=テキスト
 サイズ={size}
-字間=0.00
+字間={kern}
 行間=0.00
 表示速度=0.00
 フォント={font}
 文字色={color}
 影・縁色={subcolor}
 文字装飾={style}
-文字揃え=左寄せ[上]
+文字揃え={align}
 B={bold}
 I={italic}
 テキスト={text}
@@ -18,7 +27,7 @@ I={italic}
 自動スクロール=0
 移動座標上に表示=0
 オブジェクトの長さを自動調節=0
-[Object.1]
+{filters}[Object.{draw_index}]
 effect.name=標準描画
 X={ox}
 Y={oy}
@@ -30,8 +39,522 @@ Group=1
 X軸回転=0.00
 Y軸回転=0.00
 Z軸回転=0.00
+拡大率={scale}
+縦横比=0.000
+透明度={alpha}
+合成モード={blend}
+";
+
+/// Filter section inserted between the テキスト and 標準描画 sections when a
+/// run carries an outline width.
+pub const OUTLINE_FILTER_TEMPLATE: &str = "[Object.{index}]
+effect.name=縁取り
+サイズ={outline_width}
+縁色={subcolor}
+";
+
+/// Group control placed above the glyphs to carry the source's motion.
+pub const GROUP_ALIAS_TEMPLATE: &str = "[Object]
+frame={start},{end}
+textsplit=glyph
+[Object.0]
+effect.name=グループ制御
+X={x}
+Y={y}
+Z=0.00
+Group=1
+中心X=0.00
+中心Y=0.00
+中心Z=0.00
+X軸回転=0.00
+Y軸回転=0.00
+Z軸回転=0.00
 拡大率=100.000
 縦横比=0.000
+透明度=0.00
+対象レイヤー数={layers}
+";
+
+/// Figure traced from the font's contours, used instead of a text object
+/// when `glyph_paths` is enabled.
+#[cfg(feature = "glyph-paths")]
+pub const PATH_ALIAS_TEMPLATE: &str = "[Object]
+frame={start},{end}
+textsplit=glyph
+[Object.0]
+effect.name=パス
+パス={path}
+色={color}
+[Object.1]
+effect.name=標準描画
+X={ox}
+Y={oy}
+Z={oz}
+Group=1
+中心X=0.00
+中心Y=0.00
+中心Z=0.00
+X軸回転=0.00
+Y軸回転=0.00
+Z軸回転=0.00
+拡大率={scale}
+縦横比=0.000
 透明度={alpha}
 合成モード={blend}
 ";
+
+#[cfg(feature = "glyph-paths")]
+use crate::metrics::{GlyphMetrics, PathSegment};
+use crate::{
+    color::rotate_hue,
+    config::GlyphOrigin,
+    layout::PlacedGlyph,
+    params::{DrawParams, TextParams},
+    parser::alignment::{HDir, TextAlignment, VDir},
+};
+
+/// Object-level values shared by every glyph split from one source object.
+pub struct AliasContext<'a> {
+    pub text: &'a TextParams,
+    pub draw: &'a DrawParams,
+    pub fallback_font: Option<&'a str>,
+    pub default_outline_width: Option<f32>,
+    pub origin: GlyphOrigin,
+}
+
+fn flag(value: Option<bool>, default: &str) -> &str {
+    match value {
+        Some(true) => "1",
+        Some(false) => "0",
+        None => default,
+    }
+}
+
+/// Fills `TEXT_ALIAS_TEMPLATE` for a single placed glyph shown from `start`
+/// to `end`.
+pub fn render_glyph_alias(
+    glyph: &PlacedGlyph,
+    start: usize,
+    end: usize,
+    ctx: &AliasContext,
+) -> String {
+    let el = glyph.element;
+
+    let mut filters = String::new();
+    if let Some(width) = el.outline_width.or(ctx.default_outline_width) {
+        filters.push_str(
+            &OUTLINE_FILTER_TEMPLATE
+                .replace("{index}", "1")
+                .replace("{outline_width}", &format!("{:.2}", width))
+                .replace("{subcolor}", &ctx.text.subcolor),
+        );
+    }
+    let draw_index = if filters.is_empty() { 1 } else { 2 };
+
+    let align = match ctx.origin {
+        GlyphOrigin::TopLeft => TextAlignment {
+            hdir: HDir::Left,
+            vdir: VDir::Top,
+            is_vert: false,
+            last_line: None,
+        },
+        GlyphOrigin::Center => TextAlignment {
+            hdir: HDir::Mid,
+            vdir: VDir::Center,
+            is_vert: false,
+            last_line: None,
+        },
+    };
+    let (ax, ay) = anchor_offset(glyph, ctx.origin);
+    let (ox, oy) = (glyph.x + ax, glyph.y + ay);
+
+    // The text goes in last, so placeholders written in it stay literal.
+    TEXT_ALIAS_TEMPLATE
+        .replace("{filters}", &filters)
+        .replace("{draw_index}", &draw_index.to_string())
+        .replace("{align}", &align.to_string())
+        .replace("{start}", &start.to_string())
+        .replace("{end}", &end.to_string())
+        .replace("{size}", &format!("{:.2}", glyph.size))
+        .replace("{kern}", &format!("{:.2}", ctx.text.kern))
+        .replace(
+            "{font}",
+            resolve_font(el.font.as_deref(), &ctx.text.font, ctx.fallback_font),
+        )
+        .replace("{color}", &glyph_color(glyph, &ctx.text.color))
+        .replace("{subcolor}", &ctx.text.subcolor)
+        .replace(
+            "{style}",
+            el.deco.as_deref().unwrap_or(&ctx.text.decoration),
+        )
+        .replace("{bold}", flag(el.is_bold, &ctx.text.bold))
+        .replace("{italic}", flag(el.is_italic, &ctx.text.italic))
+        .replace("{ox}", &format!("{:.2}", ox))
+        .replace("{oy}", &format!("{:.2}", oy))
+        .replace("{oz}", &format!("{:.2}", ctx.draw.z))
+        .replace("{scale}", &format!("{:.3}", el.scale.unwrap_or(100.0)))
+        .replace("{alpha}", &ctx.draw.alpha)
+        .replace("{blend}", &ctx.draw.blend)
+        .replace("{text}", &glyph.text)
+}
+
+/// How far the point a glyph object is anchored at, which is also its
+/// transform pivot, lies from the glyph's top-left corner. The layout places
+/// glyphs by that corner; a centered glyph is anchored half its extent in.
+pub fn anchor_offset(glyph: &PlacedGlyph, origin: GlyphOrigin) -> (f32, f32) {
+    match origin {
+        GlyphOrigin::TopLeft => (0.0, 0.0),
+        GlyphOrigin::Center => (glyph.width / 2.0, glyph.size / 2.0),
+    }
+}
+
+/// Traces every character of `glyph` as SVG-style path data relative to its
+/// anchor, each shifted by the advances before it. `None` when none of them
+/// has contours.
+#[cfg(feature = "glyph-paths")]
+pub fn glyph_path_data(
+    glyph: &PlacedGlyph,
+    kern: f32,
+    origin: GlyphOrigin,
+    metrics: &dyn GlyphMetrics,
+) -> Option<String> {
+    let (ax, ay) = anchor_offset(glyph, origin);
+    let mut commands = Vec::new();
+    let mut dx = -ax;
+    for c in glyph.text.chars() {
+        let point = |(x, y): (f32, f32)| format!("{:.2} {:.2}", x + dx, y - ay);
+        for segment in metrics.outline(c, glyph.size).unwrap_or_default() {
+            commands.push(match segment {
+                PathSegment::Move(to) => format!("M {}", point(to)),
+                PathSegment::Line(to) => format!("L {}", point(to)),
+                PathSegment::Quad(ctrl, to) => format!("Q {} {}", point(ctrl), point(to)),
+                PathSegment::Cubic(c1, c2, to) => {
+                    format!("C {} {} {}", point(c1), point(c2), point(to))
+                }
+                PathSegment::Close => "Z".to_string(),
+            });
+        }
+        dx += metrics.advance(c, glyph.size) + kern;
+    }
+    (!commands.is_empty()).then(|| commands.join(" "))
+}
+
+/// Fills `PATH_ALIAS_TEMPLATE` for a glyph traced as `path` by
+/// `glyph_path_data`.
+#[cfg(feature = "glyph-paths")]
+pub fn render_path_alias(
+    glyph: &PlacedGlyph,
+    path: &str,
+    start: usize,
+    end: usize,
+    ctx: &AliasContext,
+) -> String {
+    let (ax, ay) = anchor_offset(glyph, ctx.origin);
+    PATH_ALIAS_TEMPLATE
+        .replace("{start}", &start.to_string())
+        .replace("{end}", &end.to_string())
+        .replace("{path}", path)
+        .replace("{color}", &glyph_color(glyph, &ctx.text.color))
+        .replace("{ox}", &format!("{:.2}", glyph.x + ax))
+        .replace("{oy}", &format!("{:.2}", glyph.y + ay))
+        .replace("{oz}", &format!("{:.2}", ctx.draw.z))
+        .replace(
+            "{scale}",
+            &format!("{:.3}", glyph.element.scale.unwrap_or(100.0)),
+        )
+        .replace("{alpha}", &ctx.draw.alpha)
+        .replace("{blend}", &ctx.draw.blend)
+}
+
+/// Fills `GROUP_ALIAS_TEMPLATE` for a group control that moves the `layers`
+/// layers below it along the `(x, y)` tracks.
+pub fn render_group_alias(x: &str, y: &str, start: usize, end: usize, layers: usize) -> String {
+    GROUP_ALIAS_TEMPLATE
+        .replace("{start}", &start.to_string())
+        .replace("{end}", &end.to_string())
+        .replace("{x}", x)
+        .replace("{y}", y)
+        .replace("{layers}", &layers.to_string())
+}
+
+/// The glyph's markup color, or the object's color with any `<hue>` applied.
+fn glyph_color(glyph: &PlacedGlyph, object_color: &str) -> String {
+    match (&glyph.color, glyph.element.hue_rotation) {
+        (Some(color), _) => color.to_string(),
+        (None, Some(degrees)) => {
+            rotate_hue(object_color, degrees).unwrap_or_else(|| object_color.to_string())
+        }
+        (None, None) => object_color.to_string(),
+    }
+}
+
+pub fn is_split_glyph(alias: &str) -> bool {
+    alias.lines().any(|line| line.trim() == GLYPH_MARKER)
+}
+
+/// Effect of the group control `GROUP_ALIAS_TEMPLATE` creates.
+pub const GROUP_EFFECT: &str = "グループ制御";
+
+/// Whether `alias` is a group control over the glyphs after it.
+pub fn is_group_control(alias: &str) -> bool {
+    alias
+        .lines()
+        .any(|line| line.trim() == format!("effect.name={}", GROUP_EFFECT))
+}
+
+/// Picks the font for a glyph: the markup override first, then the object's
+/// font, then the configured fallback when both are empty.
+pub fn resolve_font<'a>(
+    el_font: Option<&'a str>,
+    obj_font: &'a str,
+    fallback: Option<&'a str>,
+) -> &'a str {
+    if let Some(font) = el_font.filter(|f| !f.trim().is_empty()) {
+        return font;
+    }
+    if !obj_font.trim().is_empty() {
+        return obj_font;
+    }
+    match fallback {
+        Some(font) => {
+            aviutl2::log::debug!("フォントが空のため {} を使用します", font);
+            font
+        }
+        None => obj_font,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        layout::{LayoutParams, place_glyphs},
+        parser::{alignment::parse_alignment, markup::parse_markup},
+    };
+
+    fn params(size: f32, kern: f32, lnsp: f32) -> LayoutParams {
+        LayoutParams {
+            x: 0.0,
+            y: 0.0,
+            size,
+            kern,
+            lnsp,
+            min_size: None,
+            max_size: None,
+            line_height: None,
+        }
+    }
+
+    fn text_params() -> TextParams {
+        TextParams {
+            size: 30.0,
+            kern: 0.0,
+            lnsp: 0.0,
+            font: "MS Gothic".to_string(),
+            color: "ffffff".to_string(),
+            subcolor: "000000".to_string(),
+            decoration: "標準文字".to_string(),
+            bold: "0".to_string(),
+            italic: "0".to_string(),
+        }
+    }
+
+    fn render_all(input: &str) -> Vec<String> {
+        render_with_origin(input, GlyphOrigin::TopLeft)
+    }
+
+    fn render_with_origin(input: &str, origin: GlyphOrigin) -> Vec<String> {
+        let elements = parse_markup(input).unwrap();
+        let text = text_params();
+        let draw = DrawParams::default();
+        let layout = params(text.size, text.kern, text.lnsp);
+        let ctx = AliasContext {
+            text: &text,
+            draw: &draw,
+            fallback_font: None,
+            default_outline_width: None,
+            origin,
+        };
+        place_glyphs(&elements, &layout, &parse_alignment("左寄せ[上]"))
+            .iter()
+            .map(|glyph| render_glyph_alias(glyph, 0, 10, &ctx))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_glyph_alias_uses_object_values() {
+        let aliases = render_all("a");
+        assert_eq!(aliases.len(), 1);
+        assert!(aliases[0].contains("テキスト=a\n"));
+        assert!(aliases[0].contains("フォント=MS Gothic\n"));
+        assert!(aliases[0].contains("文字装飾=標準文字\n"));
+        assert!(aliases[0].contains("B=0\n"));
+    }
+
+    #[test]
+    fn test_render_glyph_alias_prefers_element_decoration() {
+        let aliases = render_all("a<deco:outline>b");
+        assert!(aliases[0].contains("文字装飾=標準文字\n"));
+        assert!(aliases[1].contains("文字装飾=縁取り文字\n"));
+    }
+
+    #[test]
+    fn test_resolve_font_prefers_element_font() {
+        assert_eq!(
+            resolve_font(Some("Arial"), "MS Gothic", Some("Meiryo")),
+            "Arial"
+        );
+        assert_eq!(resolve_font(None, "MS Gothic", Some("Meiryo")), "MS Gothic");
+    }
+
+    #[test]
+    fn test_resolve_font_fallback_for_empty_object_font() {
+        assert_eq!(resolve_font(None, "", Some("Meiryo")), "Meiryo");
+        assert_eq!(resolve_font(Some(""), "", Some("Meiryo")), "Meiryo");
+        assert_eq!(resolve_font(Some("  "), " ", Some("Meiryo")), "Meiryo");
+        assert_eq!(resolve_font(None, "", None), "");
+    }
+
+    #[test]
+    fn test_generated_aliases_are_marked() {
+        let aliases = render_all("ab");
+        assert!(aliases.iter().all(|alias| is_split_glyph(alias)));
+    }
+
+    #[test]
+    fn test_user_alias_is_not_marked() {
+        let alias = "[Object]\nframe=0,10\n[Object.0]\neffect.name=テキスト\nテキスト=ab\n";
+        assert!(!is_split_glyph(alias));
+    }
+
+    #[test]
+    fn test_render_glyph_alias_scale() {
+        let aliases = render_all("a<scale150>b");
+        assert!(aliases[0].contains("拡大率=100.000\n"));
+        assert!(aliases[1].contains("拡大率=150.000\n"));
+    }
+
+    #[test]
+    fn test_render_glyph_alias_origin_modes() {
+        let top_left = render_with_origin("ab", GlyphOrigin::TopLeft);
+        assert!(top_left[1].contains("文字揃え=左寄せ[上]\n"));
+        assert!(top_left[1].contains("X=30.00\nY=0.00\n"));
+
+        let center = render_with_origin("ab", GlyphOrigin::Center);
+        assert!(center[1].contains("文字揃え=中央揃え[中]\n"));
+        assert!(center[1].contains("X=45.00\nY=15.00\n"));
+    }
+
+    #[test]
+    fn test_anchor_offset_is_half_the_glyph() {
+        let elements = parse_markup("a<s20>b").unwrap();
+        let layout = params(30.0, 0.0, 0.0);
+        let glyphs = place_glyphs(&elements, &layout, &parse_alignment("左寄せ[上]"));
+        assert_eq!(anchor_offset(&glyphs[0], GlyphOrigin::TopLeft), (0.0, 0.0));
+        assert_eq!(anchor_offset(&glyphs[0], GlyphOrigin::Center), (15.0, 15.0));
+        assert_eq!(anchor_offset(&glyphs[1], GlyphOrigin::Center), (10.0, 10.0));
+    }
+
+    #[test]
+    fn test_render_glyph_alias_rotates_object_color() {
+        let aliases = render_all("a<hue120>b");
+        assert!(aliases[0].contains("文字色=ffffff\n"));
+        assert!(aliases[1].contains("文字色=ffffff\n"));
+        let mut text = text_params();
+        text.color = "ff0000".to_string();
+        let elements = parse_markup("<hue120>a").unwrap();
+        let glyphs = place_glyphs(
+            &elements,
+            &params(30.0, 0.0, 0.0),
+            &parse_alignment("左寄せ[上]"),
+        );
+        assert_eq!(glyph_color(&glyphs[0], &text.color), "00ff00");
+    }
+
+    #[test]
+    fn test_render_glyph_alias_outline_width() {
+        let aliases = render_all("a<ow3>b");
+        assert!(!aliases[0].contains("effect.name=縁取り"));
+        assert!(aliases[0].contains("[Object.1]\neffect.name=標準描画\n"));
+        assert!(aliases[1].contains("[Object.1]\neffect.name=縁取り\nサイズ=3.00\n縁色=000000\n"));
+        assert!(aliases[1].contains("[Object.2]\neffect.name=標準描画\n"));
+    }
+
+    /// Every glyph but a space is a unit square scaled to its size.
+    #[cfg(feature = "glyph-paths")]
+    struct Squares;
+
+    #[cfg(feature = "glyph-paths")]
+    impl GlyphMetrics for Squares {
+        fn advance(&self, _c: char, size: f32) -> f32 {
+            size
+        }
+
+        fn outline(&self, c: char, size: f32) -> Option<Vec<PathSegment>> {
+            (c != ' ').then(|| {
+                vec![
+                    PathSegment::Move((0.0, 0.0)),
+                    PathSegment::Line((size, 0.0)),
+                    PathSegment::Line((size, size)),
+                    PathSegment::Close,
+                ]
+            })
+        }
+    }
+
+    #[cfg(feature = "glyph-paths")]
+    #[test]
+    fn test_glyph_path_data_shifts_by_advance() {
+        let elements = parse_markup("a b").unwrap();
+        let layout = params(10.0, 2.0, 0.0);
+        let glyphs = crate::layout::group_words(place_glyphs(
+            &elements,
+            &layout,
+            &parse_alignment("左寄せ[上]"),
+        ));
+        assert_eq!(
+            glyph_path_data(&glyphs[0], 2.0, GlyphOrigin::TopLeft, &Squares).as_deref(),
+            Some("M 0.00 0.00 L 10.00 0.00 L 10.00 10.00 Z")
+        );
+
+        let mut word = glyphs[0].clone();
+        word.text = "ab".to_string();
+        let path = glyph_path_data(&word, 2.0, GlyphOrigin::TopLeft, &Squares).unwrap();
+        assert!(path.ends_with("M 12.00 0.00 L 22.00 0.00 L 22.00 10.00 Z"));
+        word.text = " ".to_string();
+        assert_eq!(
+            glyph_path_data(&word, 2.0, GlyphOrigin::TopLeft, &Squares),
+            None
+        );
+
+        let path = glyph_path_data(&glyphs[0], 2.0, GlyphOrigin::Center, &Squares);
+        assert_eq!(
+            path.as_deref(),
+            Some("M -5.00 -5.00 L 5.00 -5.00 L 5.00 5.00 Z")
+        );
+    }
+
+    #[cfg(feature = "glyph-paths")]
+    #[test]
+    fn test_render_path_alias() {
+        let text = text_params();
+        let draw = DrawParams::default();
+        let ctx = AliasContext {
+            text: &text,
+            draw: &draw,
+            fallback_font: None,
+            default_outline_width: None,
+            origin: GlyphOrigin::TopLeft,
+        };
+        let elements = parse_markup("a").unwrap();
+        let layout = LayoutParams {
+            x: 5.0,
+            ..params(30.0, 0.0, 0.0)
+        };
+        let glyphs = place_glyphs(&elements, &layout, &parse_alignment("左寄せ[上]"));
+        let alias = render_path_alias(&glyphs[0], "M 0.00 0.00 Z", 0, 10, &ctx);
+        assert!(alias.contains("effect.name=パス\nパス=M 0.00 0.00 Z\n色=ffffff\n"));
+        assert!(alias.contains("X=5.00\n"));
+        assert!(is_split_glyph(&alias));
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+/// Failures while splitting, converted to `AnyResult` at the menu boundary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SplitError {
+    /// The markup couldn't be parsed.
+    Parse { text: String, message: String },
+    /// A required effect item couldn't be read from the object.
+    MissingItem { effect: String, item: String },
+    /// An effect item that should be numeric couldn't be parsed.
+    InvalidNumber {
+        effect: String,
+        item: String,
+        value: String,
+    },
+    /// The menu was run without any object selected.
+    NoSelection,
+    /// A glyph's alias is longer than `MAX_ALIAS_BYTES`, e.g. from an
+    /// extremely long font name; `text` is the start of the glyph's text.
+    AliasTooLong {
+        text: String,
+        length: usize,
+        limit: usize,
+    },
+    /// No free layer was found for a glyph.
+    LayerOverflow { layer: usize },
+    /// Object creation kept failing, most likely because the host rejects
+    /// it outright rather than because the layers are occupied.
+    CreateFailed {
+        layer: usize,
+        attempts: usize,
+        message: String,
+    },
+    /// A host call failed.
+    Host(String),
+    /// The exported aliases couldn't be written.
+    Export { path: String, message: String },
+}
+
+impl SplitError {
+    pub fn host(error: impl fmt::Display) -> SplitError {
+        SplitError::Host(error.to_string())
+    }
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SplitError::Parse { text, message } => {
+                write!(f, "テキストの解析に失敗しました: {}: {}", text, message)
+            }
+            SplitError::MissingItem { effect, item } => {
+                write!(f, "{} の {} を取得できませんでした", effect, item)
+            }
+            SplitError::InvalidNumber {
+                effect,
+                item,
+                value,
+            } => {
+                write!(f, "{} の {} が数値ではありません: {}", effect, item, value)
+            }
+            SplitError::NoSelection => write!(f, "オブジェクトが選択されていません"),
+            SplitError::AliasTooLong {
+                text,
+                length,
+                limit,
+            } => write!(
+                f,
+                "「{}」のオブジェクトの設定が {} バイトあり、上限の {} バイトを超えています。フォント名やテキストが長すぎないか確認してください",
+                text, length, limit
+            ),
+            SplitError::LayerOverflow { layer } => {
+                write!(f, "レイヤー {} 以降に空きがありません", layer)
+            }
+            SplitError::CreateFailed {
+                layer,
+                attempts,
+                message,
+            } => write!(
+                f,
+                "レイヤー {} から {} 回続けてオブジェクトを作成できませんでした: {}",
+                layer, attempts, message
+            ),
+            SplitError::Host(message) => write!(f, "{}", message),
+            SplitError::Export { path, message } => {
+                write!(f, "{} に書き出せませんでした: {}", path, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_message() {
+        let error = SplitError::Parse {
+            text: "<s".to_string(),
+            message: "unexpected end".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "テキストの解析に失敗しました: <s: unexpected end"
+        );
+    }
+}
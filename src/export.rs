@@ -0,0 +1,128 @@
+use crate::split::CreationInfo;
+
+/// Joins the planned objects into one multi-object alias (`.object` file)
+/// that can be dropped onto a timeline. `[Object]` and `[Object.N]` become
+/// `[i]` and `[i.N]`, and each object records the layer it was planned on.
+pub fn to_object_file(infos: &[CreationInfo]) -> String {
+    let mut file = String::new();
+    for (index, (alias, layer, ..)) in infos.iter().enumerate() {
+        for line in alias.lines() {
+            if line == "[Object]" {
+                file.push_str(&format!("[{}]\nlayer={}\n", index, layer));
+            } else if let Some(rest) = line.strip_prefix("[Object.") {
+                file.push_str(&format!("[{}.{}\n", index, rest));
+            } else {
+                file.push_str(line);
+                file.push('\n');
+            }
+        }
+    }
+    file
+}
+
+/// The value of the first `item=` line in `alias`.
+fn alias_item<'a>(alias: &'a str, item: &str) -> Option<&'a str> {
+    alias
+        .lines()
+        .find_map(|line| line.strip_prefix(item)?.strip_prefix('='))
+}
+
+/// Quotes a CSV field when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Lists the planned objects as CSV, one row per object, for driving other
+/// tools. Values are read back from each alias, so objects without text,
+/// like group controls, have an empty `char`.
+pub fn creation_infos_to_csv(infos: &[CreationInfo]) -> String {
+    let mut csv = String::from("index,char,layer,x,y,z,size,color,frame,length\n");
+    for (index, (alias, layer, frame, length)) in infos.iter().enumerate() {
+        let item = |item| alias_item(alias, item).unwrap_or_default();
+        let color = alias_item(alias, "文字色").unwrap_or_else(|| item("色"));
+        let row = [
+            index.to_string(),
+            csv_field(item("テキスト")),
+            layer.to_string(),
+            item("X").to_string(),
+            item("Y").to_string(),
+            item("Z").to_string(),
+            item("サイズ").to_string(),
+            color.to_string(),
+            frame.to_string(),
+            length.to_string(),
+        ];
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::SplitConfig,
+        params::{DrawParams, TextParams},
+        parser::alignment::TextAlignment,
+        split::{SplitOptions, SplitParams, compute_creation_infos},
+    };
+
+    #[test]
+    fn test_creation_infos_to_csv() {
+        let params = SplitParams {
+            text: TextParams {
+                size: 30.0,
+                kern: 2.0,
+                ..TextParams::default()
+            },
+            draw: DrawParams::default(),
+            layer: 3,
+            start: 10,
+            end: 70,
+        };
+        let infos = compute_creation_infos(
+            "a<#ff0000>,",
+            &params,
+            &TextAlignment::default(),
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            creation_infos_to_csv(&infos),
+            "index,char,layer,x,y,z,size,color,frame,length\n\
+             0,a,4,0.00,0.00,0.00,30.00,ffffff,10,60\n\
+             1,\",\",5,32.00,0.00,0.00,30.00,ff0000,10,60\n"
+        );
+    }
+
+    #[test]
+    fn test_to_object_file() {
+        let infos = vec![
+            (
+                "[Object]\nframe=0,10\n[Object.0]\neffect.name=テキスト\nテキスト=a\n".to_string(),
+                2,
+                0,
+                10,
+            ),
+            (
+                "[Object]\nframe=5,10\n[Object.0]\neffect.name=テキスト\nテキスト=b\n[Object.1]\neffect.name=標準描画\n"
+                    .to_string(),
+                3,
+                5,
+                5,
+            ),
+        ];
+        assert_eq!(
+            to_object_file(&infos),
+            "[0]\nlayer=2\nframe=0,10\n[0.0]\neffect.name=テキスト\nテキスト=a\n\
+             [1]\nlayer=3\nframe=5,10\n[1.0]\neffect.name=テキスト\nテキスト=b\n\
+             [1.1]\neffect.name=標準描画\n"
+        );
+    }
+}
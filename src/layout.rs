@@ -0,0 +1,1112 @@
+use crate::{
+    metrics::{Approximate, GlyphMetrics},
+    parser::{
+        alignment::{HDir, TextAlignment, VDir},
+        markup::{NEWLINE, SizeSpec, TextElement},
+    },
+};
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// Object-level values the layout is computed against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutParams {
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub kern: f32,
+    pub lnsp: f32,
+    /// Bounds every glyph size is clamped into, whatever the markup says.
+    pub min_size: Option<f32>,
+    pub max_size: Option<f32>,
+    /// Lines advance by this multiple of their tallest glyph instead of by
+    /// `size + lnsp`.
+    pub line_height: Option<f32>,
+}
+
+impl LayoutParams {
+    /// Returns the element's size, clamped into `min_size..=max_size`.
+    pub fn glyph_size(&self, el: &TextElement) -> f32 {
+        let size = el.effective_size(self.size);
+        let clamped = self
+            .max_size
+            .map_or(size, |max| size.min(max))
+            .max(self.min_size.unwrap_or(f32::MIN));
+        if clamped != size {
+            aviutl2::log::debug!("サイズ {} を {} に制限しました", size, clamped);
+        }
+        clamped
+    }
+
+    /// How far the line after one whose tallest glyph is `line_size` starts
+    /// below it. Empty lines are as tall as the base size.
+    fn line_advance(&self, line_size: f32) -> f32 {
+        match self.line_height {
+            Some(multiple) if line_size > 0.0 => line_size * multiple,
+            Some(multiple) => self.size * multiple,
+            None => self.size + self.lnsp,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedGlyph<'a> {
+    pub element: &'a TextElement,
+    pub text: String,
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    /// Horizontal extent; wider than `size` when the object holds a whole run.
+    pub width: f32,
+    /// Color from the markup, with any palette already applied.
+    pub color: Option<Cow<'a, str>>,
+    /// Position in the element's `<timing>` list.
+    pub timing_index: Option<usize>,
+}
+
+impl PlacedGlyph<'_> {
+    /// Whether the object would only contain whitespace.
+    pub fn is_blank(&self) -> bool {
+        self.text.chars().all(char::is_whitespace)
+    }
+
+    /// Frames from the range start until the glyph appears, per `<timing>`.
+    /// Past the end of the list, `default` is used, or else the last value.
+    pub fn frame_offset(&self, default: Option<usize>) -> Option<usize> {
+        let timings = self.element.timings.as_deref()?;
+        let index = self.timing_index?;
+        timings
+            .get(index)
+            .copied()
+            .or(default)
+            .or(timings.last().copied())
+    }
+
+    pub fn bounding_box(&self) -> GlyphBox {
+        GlyphBox {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.size,
+            text: self.text.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GlyphBox {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub text: String,
+}
+
+fn is_newline(el: &TextElement) -> bool {
+    el.text == NEWLINE
+}
+
+/// Zero-width joiners and variation selectors, which only modify the glyph
+/// before them and so neither advance nor become objects of their own.
+fn is_joiner(c: char) -> bool {
+    c == '\u{200d}' || ('\u{fe00}'..='\u{fe0f}').contains(&c)
+}
+
+/// Whether the element becomes a single object instead of one per glyph.
+/// A `<nosplit>` region always stays whole. Once the text has a `<split>`
+/// region, everything outside it does too.
+fn keeps_whole(el: &TextElement, has_split_regions: bool) -> bool {
+    match el.split {
+        Some(split) => !split,
+        None => has_split_regions,
+    }
+}
+
+/// Counts the glyphs since a list-valued tag last changed or was repeated,
+/// so that the list is indexed from the first glyph it applies to.
+struct ListCursor<'a, T> {
+    current: Option<&'a [T]>,
+    /// The element the previous glyph came from; a restart only applies on
+    /// the first glyph of the element its tag opened.
+    element: Option<&'a TextElement>,
+    index: usize,
+}
+
+impl<'a, T: PartialEq> ListCursor<'a, T> {
+    fn new() -> Self {
+        ListCursor {
+            current: None,
+            element: None,
+            index: 0,
+        }
+    }
+
+    fn next(
+        &mut self,
+        element: &'a TextElement,
+        list: Option<&'a [T]>,
+        restarts: bool,
+    ) -> Option<usize> {
+        let list = list.filter(|list| !list.is_empty());
+        let entered = !self.element.is_some_and(|prev| std::ptr::eq(prev, element));
+        if self.current != list || (restarts && entered) {
+            self.current = list;
+            self.index = 0;
+        }
+        self.element = Some(element);
+        list?;
+        self.index += 1;
+        Some(self.index - 1)
+    }
+}
+
+/// Returns the `(width, height)` of the text block. The block ends with its
+/// last line that has glyphs: a trailing newline draws nothing, so it
+/// doesn't add a line the alignment would have to make room for.
+pub fn measure_text(elements: &[TextElement], params: &LayoutParams) -> (f32, f32) {
+    measure_text_with(elements, params, &Approximate)
+}
+
+/// Like `measure_text`, advancing by `metrics` for every glyph.
+pub fn measure_text_with(
+    elements: &[TextElement],
+    params: &LayoutParams,
+    metrics: &dyn GlyphMetrics,
+) -> (f32, f32) {
+    let width = line_widths(elements, params, metrics)
+        .into_iter()
+        .fold(0.0, f32::max);
+    // Every line is as tall as its largest glyph, the last one included.
+    let mut h: f32 = 0.0;
+    let mut h_temp: f32 = 0.0;
+    let mut bottom: f32 = 0.0;
+    for el in elements {
+        if is_newline(el) {
+            h += match params.line_height {
+                Some(_) => params.line_advance(h_temp),
+                None => h_temp + params.lnsp,
+            };
+            h_temp = 0.0;
+            continue;
+        }
+        h_temp = h_temp.max(params.glyph_size(el));
+        bottom = h + h_temp;
+    }
+    (width, bottom)
+}
+
+/// Returns the width of every line, from its first glyph's left edge to its
+/// last glyph's right edge.
+fn line_widths(
+    elements: &[TextElement],
+    params: &LayoutParams,
+    metrics: &dyn GlyphMetrics,
+) -> Vec<f32> {
+    let mut widths = vec![0.0];
+    let mut glyphs = 0;
+    for el in elements {
+        if is_newline(el) {
+            widths.push(0.0);
+            glyphs = 0;
+            continue;
+        }
+        let size = params.glyph_size(el);
+        for c in el.text.chars().filter(|&c| !is_joiner(c)) {
+            let width = widths.last_mut().expect("starts with a line");
+            if glyphs > 0 {
+                *width += params.kern;
+            }
+            *width += metrics.advance(c, size);
+            glyphs += 1;
+        }
+    }
+    widths
+}
+
+/// Trims the whitespace at both ends of every line, so that stray spaces
+/// don't shift aligned lines. Spaces inside a line are kept.
+pub fn trim_lines(elements: &[TextElement]) -> Vec<TextElement> {
+    let mut trimmed = Vec::with_capacity(elements.len());
+    for chunk in elements.split_inclusive(is_newline) {
+        let (body, newline) = match chunk.split_last() {
+            Some((last, body)) if is_newline(last) => (body, Some(last)),
+            _ => (chunk, None),
+        };
+        let mut line = body.to_vec();
+        while let Some(first) = line.first_mut() {
+            let text = first.text.trim_start();
+            if !text.is_empty() {
+                first.text = text.to_string();
+                break;
+            }
+            line.remove(0);
+        }
+        while let Some(last) = line.last_mut() {
+            let text = last.text.trim_end();
+            if !text.is_empty() {
+                last.text = text.to_string();
+                break;
+            }
+            line.pop();
+        }
+        trimmed.extend(line);
+        trimmed.extend(newline.cloned());
+    }
+    trimmed
+}
+
+/// Gives the `<sauto>` runs of every line the size that makes the line
+/// exactly `target_width` wide. Runs sharing a line share that size. Lines
+/// whose other glyphs are already that wide keep the base size.
+pub fn fit_auto_sizes(
+    elements: &[TextElement],
+    params: &LayoutParams,
+    target_width: f32,
+    metrics: &dyn GlyphMetrics,
+) -> Vec<TextElement> {
+    // Unclamped, so that the width grows linearly with the size.
+    let unclamped = LayoutParams {
+        min_size: None,
+        max_size: None,
+        ..params.clone()
+    };
+    let width_at = |line: &[TextElement], size: f32| {
+        let line: Vec<TextElement> = line
+            .iter()
+            .map(|el| match el.size {
+                Some(SizeSpec::Auto) => TextElement {
+                    size: Some(SizeSpec::Absolute(size)),
+                    ..el.clone()
+                },
+                _ => el.clone(),
+            })
+            .collect();
+        line_widths(&line, &unclamped, metrics)[0]
+    };
+    let mut fitted = Vec::with_capacity(elements.len());
+    for line in elements
+        .split_inclusive(is_newline)
+        .flat_map(|line| line.chunk_by(|a, b| a.page_index == b.page_index))
+    {
+        if !line.iter().any(|el| el.size == Some(SizeSpec::Auto)) {
+            fitted.extend_from_slice(line);
+            continue;
+        }
+        let fixed = width_at(line, 0.0);
+        let per_size = width_at(line, 1.0) - fixed;
+        let size = (target_width - fixed) / per_size;
+        let size = if size.is_finite() && size > 0.0 {
+            SizeSpec::Absolute(size)
+        } else {
+            SizeSpec::Base
+        };
+        fitted.extend(line.iter().map(|el| match el.size {
+            Some(SizeSpec::Auto) => TextElement {
+                size: Some(size),
+                ..el.clone()
+            },
+            _ => el.clone(),
+        }));
+    }
+    fitted
+}
+
+/// Returns the size of every line's tallest glyph, 0 for empty lines.
+fn line_sizes(elements: &[TextElement], params: &LayoutParams) -> Vec<f32> {
+    let mut sizes = vec![0.0];
+    for el in elements {
+        if is_newline(el) {
+            sizes.push(0.0);
+            continue;
+        }
+        let size = sizes.last_mut().expect("starts with a line");
+        *size = f32::max(*size, params.glyph_size(el));
+    }
+    sizes
+}
+
+/// Returns how far the block's top-left corner sits from the object origin.
+fn alignment_offset(width: f32, height: f32, alignment: &TextAlignment) -> (f32, f32) {
+    let w = match alignment.hdir {
+        HDir::Left => 0.0,
+        HDir::Mid => width * 0.5,
+        HDir::Right => width,
+    };
+    let h = match alignment.vdir {
+        VDir::Top => 0.0,
+        VDir::Center => height * 0.5,
+        VDir::Bottom => height,
+    };
+    (w, h)
+}
+
+/// Computes the position of every glyph without touching the host.
+pub fn place_glyphs<'a>(
+    elements: &'a [TextElement],
+    params: &LayoutParams,
+    alignment: &TextAlignment,
+) -> Vec<PlacedGlyph<'a>> {
+    place_glyphs_with(elements, params, alignment, &Approximate)
+}
+
+/// Like `place_glyphs`, advancing by `metrics` for every glyph.
+pub fn place_glyphs_with<'a>(
+    elements: &'a [TextElement],
+    params: &LayoutParams,
+    alignment: &TextAlignment,
+    metrics: &dyn GlyphMetrics,
+) -> Vec<PlacedGlyph<'a>> {
+    let (_, height) = measure_text_with(elements, params, metrics);
+    let (_, h) = alignment_offset(0.0, height, alignment);
+    // Each line is aligned on its own, so shorter lines of centered text
+    // share the center of the longer ones.
+    let widths = line_widths(elements, params, metrics);
+    let last_line = widths.len() - 1;
+    let line_x = |line: usize| {
+        let alignment = match alignment.last_line {
+            Some(hdir) if line == last_line => &TextAlignment {
+                hdir,
+                ..alignment.clone()
+            },
+            _ => alignment,
+        };
+        params.x - alignment_offset(widths[line], 0.0, alignment).0
+    };
+    let sizes = line_sizes(elements, params);
+
+    let has_split_regions = elements.iter().any(|el| el.split == Some(true));
+
+    let mut glyphs = Vec::new();
+    let mut palette = ListCursor::new();
+    let mut wave = ListCursor::new();
+    let mut next_color = |el: &'a TextElement| {
+        let wave_index = wave.next(
+            el,
+            el.color_wave.as_ref().map(std::slice::from_ref),
+            el.restarts.color_wave,
+        );
+        let palette_index = palette.next(el, el.palette.as_deref(), el.restarts.palette);
+        if let (Some(wave), Some(index)) = (&el.color_wave, wave_index) {
+            return wave.color_at(index).map(Cow::Owned);
+        }
+        match (&el.palette, palette_index) {
+            (Some(colors), Some(index)) => {
+                Some(Cow::Borrowed(colors[index % colors.len()].as_str()))
+            }
+            _ => el.color.as_deref().map(Cow::Borrowed),
+        }
+    };
+    let mut timing = ListCursor::new();
+    let mut line = 0;
+    let mut x = line_x(line);
+    let mut y = params.y - h;
+    let mut line_start = 0;
+    for el in elements {
+        if is_newline(el) {
+            y += params.line_advance(sizes[line]);
+            line += 1;
+            x = line_x(line);
+            line_start = glyphs.len();
+            continue;
+        }
+        let size = params.glyph_size(el);
+        let glyph_y = y + el.baseline_shift.unwrap_or(0.0);
+        if keeps_whole(el, has_split_regions) {
+            let advance: f32 = el
+                .text
+                .chars()
+                .filter(|&c| !is_joiner(c))
+                .map(|c| metrics.advance(c, size) + params.kern)
+                .sum();
+            glyphs.push(PlacedGlyph {
+                element: el,
+                text: el.text.clone(),
+                x,
+                y: glyph_y,
+                size,
+                width: advance - params.kern,
+                color: next_color(el),
+                timing_index: timing.next(el, el.timings.as_deref(), el.restarts.timings),
+            });
+            x += advance;
+            continue;
+        }
+        for c in el.text.chars() {
+            if is_joiner(c) {
+                // One at the start of a line has nothing to modify.
+                if glyphs.len() > line_start {
+                    glyphs.last_mut().expect("line has glyphs").text.push(c);
+                }
+                continue;
+            }
+            let advance = metrics.advance(c, size);
+            glyphs.push(PlacedGlyph {
+                element: el,
+                text: c.to_string(),
+                x,
+                y: glyph_y,
+                size,
+                width: advance,
+                color: next_color(el),
+                timing_index: timing.next(el, el.timings.as_deref(), el.restarts.timings),
+            });
+            x += advance + params.kern;
+        }
+    }
+    glyphs
+}
+
+/// Merges the glyphs of each word into one object and drops the whitespace
+/// between words. A word never spans runs, since one object has one style.
+/// `<timing>` lists then count words rather than glyphs.
+pub fn group_words(glyphs: Vec<PlacedGlyph>) -> Vec<PlacedGlyph> {
+    let mut words: Vec<PlacedGlyph> = Vec::new();
+    let mut in_word = false;
+    for glyph in glyphs {
+        if glyph.is_blank() {
+            in_word = false;
+            continue;
+        }
+        match words.last_mut() {
+            Some(word) if in_word && std::ptr::eq(word.element, glyph.element) => {
+                word.text.push_str(&glyph.text);
+                word.width = glyph.x + glyph.width - word.x;
+            }
+            _ => words.push(glyph),
+        }
+        in_word = true;
+    }
+    let mut timing = ListCursor::new();
+    for word in &mut words {
+        let element = word.element;
+        word.timing_index = timing.next(
+            element,
+            element.timings.as_deref(),
+            element.restarts.timings,
+        );
+    }
+    words
+}
+
+/// Reverses the glyphs of every line while keeping the line's positions, so
+/// the last glyph takes the first one's place and is created first. Unlike
+/// right-to-left text, the positions themselves stay where the layout put
+/// them; only which glyph sits at each one changes.
+pub fn reverse_lines(glyphs: &mut [PlacedGlyph]) {
+    let mut rest = glyphs;
+    while let Some(first) = rest.first() {
+        let line_len = rest
+            .iter()
+            .take_while(|glyph| glyph.element.line_index == first.element.line_index)
+            .count();
+        let (line, next) = rest.split_at_mut(line_len);
+        rest = next;
+
+        let xs: Vec<f32> = line.iter().map(|glyph| glyph.x).collect();
+        line.reverse();
+        for (glyph, x) in line.iter_mut().zip(xs) {
+            glyph.x = x;
+        }
+    }
+}
+
+/// Merges the glyphs of every line into `count` runs of roughly equal
+/// length, fewer for lines shorter than that. A chunk never spans runs, so
+/// lines with several styles may give more objects.
+pub fn group_chunks(glyphs: Vec<PlacedGlyph>, count: usize) -> Vec<PlacedGlyph> {
+    let count = count.max(1);
+    group_line_parts(glyphs, |len| {
+        let parts = count.min(len);
+        (0..parts)
+            .map(|part| len / parts + usize::from(part < len % parts))
+            .collect()
+    })
+}
+
+/// Merges the glyphs of every line into runs of `size` glyphs, the last one
+/// shorter when they don't divide evenly. Like `group_chunks`, a chunk never
+/// spans runs.
+pub fn group_fixed_chunks(glyphs: Vec<PlacedGlyph>, size: usize) -> Vec<PlacedGlyph> {
+    let size = size.max(1);
+    group_line_parts(glyphs, |len| {
+        (0..len)
+            .step_by(size)
+            .map(|start| size.min(len - start))
+            .collect()
+    })
+}
+
+/// Merges the glyphs of every line into consecutive parts, as long as
+/// `part_lens` gives for the line's glyph count.
+fn group_line_parts<'a>(
+    glyphs: Vec<PlacedGlyph<'a>>,
+    part_lens: impl Fn(usize) -> Vec<usize>,
+) -> Vec<PlacedGlyph<'a>> {
+    let mut chunks: Vec<PlacedGlyph> = Vec::new();
+    let mut rest = glyphs.as_slice();
+    while let Some(first) = rest.first() {
+        let line_len = rest
+            .iter()
+            .take_while(|glyph| glyph.element.line_index == first.element.line_index)
+            .count();
+        let (line, next) = rest.split_at(line_len);
+        rest = next;
+
+        let mut start = 0;
+        for len in part_lens(line.len()) {
+            for (index, glyph) in line[start..start + len].iter().enumerate() {
+                match chunks.last_mut() {
+                    Some(chunk) if index > 0 && std::ptr::eq(chunk.element, glyph.element) => {
+                        chunk.text.push_str(&glyph.text);
+                        chunk.width = glyph.x + glyph.width - chunk.x;
+                    }
+                    _ => chunks.push(glyph.clone()),
+                }
+            }
+            start += len;
+        }
+    }
+    let mut timing = ListCursor::new();
+    for chunk in &mut chunks {
+        let element = chunk.element;
+        chunk.timing_index = timing.next(
+            element,
+            element.timings.as_deref(),
+            element.restarts.timings,
+        );
+    }
+    chunks
+}
+
+/// Multiplies positions and sizes by `factor`. The layout is linear in the
+/// object position, sizes, kern and lnsp, so this equals laying out with all
+/// of them scaled.
+pub fn scale_glyphs(glyphs: &mut [PlacedGlyph], factor: f32) {
+    for glyph in glyphs {
+        glyph.x *= factor;
+        glyph.y *= factor;
+        glyph.size *= factor;
+        glyph.width *= factor;
+    }
+}
+
+/// Returns each glyph's rectangle, for callers that only need positions.
+pub fn glyph_boxes(
+    elements: &[TextElement],
+    params: &LayoutParams,
+    alignment: &TextAlignment,
+) -> Vec<GlyphBox> {
+    place_glyphs(elements, params, alignment)
+        .iter()
+        .map(PlacedGlyph::bounding_box)
+        .collect()
+}
+
+/// Renders the layout as an SVG document with one `<text>` per glyph, to
+/// check positions in a browser instead of the host. The view box covers
+/// every glyph's rectangle.
+pub fn layout_to_svg(
+    elements: &[TextElement],
+    params: &LayoutParams,
+    alignment: &TextAlignment,
+) -> String {
+    let glyphs = place_glyphs(elements, params, alignment);
+    let (mut left, mut top) = (f32::MAX, f32::MAX);
+    let (mut right, mut bottom) = (f32::MIN, f32::MIN);
+    for glyph in &glyphs {
+        left = left.min(glyph.x);
+        top = top.min(glyph.y);
+        right = right.max(glyph.x + glyph.width);
+        bottom = bottom.max(glyph.y + glyph.size);
+    }
+    if glyphs.is_empty() {
+        (left, top, right, bottom) = (params.x, params.y, params.x, params.y);
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{:.2} {:.2} {:.2} {:.2}\">\n",
+        left,
+        top,
+        right - left,
+        bottom - top
+    );
+    for glyph in &glyphs {
+        let el = glyph.element;
+        let mut attributes = format!(
+            "x=\"{:.2}\" y=\"{:.2}\" font-size=\"{:.2}\" dominant-baseline=\"text-before-edge\"",
+            glyph.x, glyph.y, glyph.size
+        );
+        if let Some(color) = &glyph.color {
+            attributes.push_str(&format!(" fill=\"#{}\"", escape_xml(color)));
+        }
+        if let Some(font) = &el.font {
+            attributes.push_str(&format!(" font-family=\"{}\"", escape_xml(font)));
+        }
+        if el.is_bold == Some(true) {
+            attributes.push_str(" font-weight=\"bold\"");
+        }
+        if el.is_italic == Some(true) {
+            attributes.push_str(" font-style=\"italic\"");
+        }
+        svg.push_str(&format!(
+            "  <text {}>{}</text>\n",
+            attributes,
+            escape_xml(&glyph.text)
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{alignment::parse_alignment, markup::parse_markup};
+
+    fn params() -> LayoutParams {
+        LayoutParams {
+            x: 100.0,
+            y: 50.0,
+            size: 10.0,
+            kern: 2.0,
+            lnsp: 4.0,
+            min_size: None,
+            max_size: None,
+            line_height: None,
+        }
+    }
+
+    #[test]
+    fn test_glyph_boxes_for_short_string() {
+        let elements = parse_markup("ab\\nc").unwrap();
+        let boxes = glyph_boxes(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let expected = [(100.0, 50.0, "a"), (112.0, 50.0, "b"), (100.0, 64.0, "c")];
+        assert_eq!(boxes.len(), expected.len());
+        for (b, (x, y, text)) in boxes.iter().zip(expected) {
+            assert_eq!((b.x, b.y, b.text.as_str()), (x, y, text));
+            assert_eq!((b.width, b.height), (10.0, 10.0));
+        }
+    }
+
+    #[test]
+    fn test_glyph_boxes_use_element_size() {
+        let elements = parse_markup("a<s20>b").unwrap();
+        let boxes = glyph_boxes(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        assert_eq!(boxes[1].x, 112.0);
+        assert_eq!(boxes[1].width, 20.0);
+    }
+
+    #[test]
+    fn test_baseline_shift_does_not_affect_line_advance() {
+        let elements = parse_markup("a<y-5>b<y>c\\nd").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let ys: Vec<f32> = glyphs.iter().map(|g| g.y).collect();
+        assert_eq!(ys, vec![50.0, 45.0, 50.0, 64.0]);
+    }
+
+    #[test]
+    fn test_literal_and_real_newlines_share_layout() {
+        let alignment = parse_alignment("中央揃え[中]");
+        let escaped = parse_markup("ab\\nc").unwrap();
+        let real = parse_markup("ab\nc").unwrap();
+        assert_eq!(
+            glyph_boxes(&escaped, &params(), &alignment),
+            glyph_boxes(&real, &params(), &alignment)
+        );
+    }
+
+    #[test]
+    fn test_crlf_leaves_no_carriage_return_in_glyphs() {
+        let elements = parse_markup("ab\r\nc").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let texts: Vec<&str> = glyphs.iter().map(|g| g.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b", "c"]);
+        assert_eq!(glyphs[2].y, 64.0);
+    }
+
+    #[test]
+    fn test_variation_selector_joins_preceding_glyph() {
+        let elements = parse_markup("\u{260e}\u{fe0f}a\\n\u{200d}b").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let texts: Vec<_> = glyphs.iter().map(|g| g.text.as_str()).collect();
+        assert_eq!(texts, ["\u{260e}\u{fe0f}", "a", "b"]);
+        assert_eq!((glyphs[1].x, glyphs[2].x), (112.0, 100.0));
+        assert_eq!(measure_text(&elements, &params()).0, 22.0);
+    }
+
+    #[test]
+    fn test_scale_does_not_affect_advance() {
+        let elements = parse_markup("a<scale200>b<scale>c").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let xs: Vec<f32> = glyphs.iter().map(|g| g.x).collect();
+        assert_eq!(xs, vec![100.0, 112.0, 124.0]);
+    }
+
+    #[test]
+    fn test_only_split_region_is_split() {
+        let elements = parse_markup("ab<split>cd</split>ef").unwrap();
+        let boxes = glyph_boxes(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let texts: Vec<&str> = boxes.iter().map(|b| b.text.as_str()).collect();
+        assert_eq!(texts, vec!["ab", "c", "d", "ef"]);
+        let xs: Vec<f32> = boxes.iter().map(|b| b.x).collect();
+        assert_eq!(xs, vec![100.0, 124.0, 136.0, 148.0]);
+        assert_eq!(boxes[0].width, 22.0);
+    }
+
+    #[test]
+    fn test_nosplit_region_stays_whole() {
+        let elements = parse_markup("ab<nosplit>cd</nosplit>ef").unwrap();
+        let boxes = glyph_boxes(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let texts: Vec<&str> = boxes.iter().map(|b| b.text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b", "cd", "e", "f"]);
+        let xs: Vec<f32> = boxes.iter().map(|b| b.x).collect();
+        assert_eq!(xs, vec![100.0, 112.0, 124.0, 148.0, 160.0]);
+        assert_eq!(boxes[2].width, 22.0);
+    }
+
+    #[test]
+    fn test_skipping_blank_glyphs_keeps_positions() {
+        let elements = parse_markup("a b").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let kept: Vec<_> = glyphs.iter().filter(|g| !g.is_blank()).collect();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].x, 100.0);
+        assert_eq!(kept[1].x, 124.0);
+    }
+
+    #[test]
+    fn test_color_wave_per_glyph() {
+        let elements = parse_markup("a<colorfn:sine,ff0000,0000ff,period=4>bc<#00ff00>de").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let colors: Vec<_> = glyphs.iter().map(|g| g.color.as_deref()).collect();
+        assert_eq!(
+            colors,
+            [
+                None,
+                Some("ff0000"),
+                Some("800080"),
+                Some("0000ff"),
+                Some("800080")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_palette_cycles_per_glyph() {
+        let elements = parse_markup("<#123456>a<palette:ff0000,00ff00>bcd\ne<palette>f").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let colors: Vec<_> = glyphs.iter().map(|g| g.color.as_deref()).collect();
+        assert_eq!(
+            colors,
+            [
+                Some("123456"),
+                Some("ff0000"),
+                Some("00ff00"),
+                Some("ff0000"),
+                Some("00ff00"),
+                Some("123456"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_list_tag_restarts_cycle() {
+        let elements = parse_markup(
+            "<palette:ff0000,00ff00><timing:0,3>ab<palette:ff0000,00ff00><timing:0,3>c<s20>d",
+        )
+        .unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let colors: Vec<_> = glyphs.iter().map(|g| g.color.as_deref()).collect();
+        assert_eq!(
+            colors,
+            [
+                Some("ff0000"),
+                Some("00ff00"),
+                Some("ff0000"),
+                Some("00ff00")
+            ]
+        );
+        let offsets: Vec<_> = glyphs.iter().map(|g| g.frame_offset(None)).collect();
+        assert_eq!(offsets, [Some(0), Some(3), Some(0), Some(3)]);
+    }
+
+    #[test]
+    fn test_scale_glyphs_doubles_layout() {
+        let elements = parse_markup("a<s20>b<y3>\\nc").unwrap();
+        let alignment = parse_alignment("中央揃え[中]");
+        let base = place_glyphs(&elements, &params(), &alignment);
+        let mut scaled = base.clone();
+        scale_glyphs(&mut scaled, 2.0);
+
+        let doubled = LayoutParams {
+            x: 200.0,
+            y: 100.0,
+            size: 20.0,
+            kern: 4.0,
+            lnsp: 8.0,
+            min_size: None,
+            max_size: None,
+            line_height: None,
+        };
+        let elements = parse_markup("a<s40>b<y6>\\nc").unwrap();
+        let expected = place_glyphs(&elements, &doubled, &alignment);
+        for ((b, s), e) in base.iter().zip(&scaled).zip(&expected) {
+            assert_eq!((s.x, s.y), (b.x * 2.0, b.y * 2.0));
+            assert_eq!((s.size, s.width), (b.size * 2.0, b.width * 2.0));
+            assert_eq!((s.x, s.y, s.size), (e.x, e.y, e.size));
+        }
+    }
+
+    #[test]
+    fn test_last_line_alignment() {
+        let elements = parse_markup("abcd\nef").unwrap();
+        let alignment = TextAlignment {
+            last_line: Some(HDir::Mid),
+            ..parse_alignment("左寄せ[上]")
+        };
+        let glyphs = place_glyphs(&elements, &params(), &alignment);
+        assert_eq!(glyphs[0].x, 100.0);
+        // `ef` is 22 wide, centered on the origin.
+        assert_eq!(glyphs[4].x, 89.0);
+        assert_eq!(glyphs[5].x, 101.0);
+    }
+
+    #[test]
+    fn test_lines_are_aligned_individually() {
+        let elements = parse_markup("ab\\ncdef").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("中央揃え[上]"));
+        let center = |line: &[PlacedGlyph]| {
+            let last = line.last().unwrap();
+            (line[0].x + last.x + last.width) / 2.0
+        };
+        assert_eq!(center(&glyphs[..2]), 100.0);
+        assert_eq!(center(&glyphs[2..]), 100.0);
+        assert_eq!(glyphs[2].x, 77.0);
+
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("右寄せ[上]"));
+        assert_eq!(glyphs[1].x + glyphs[1].width, 100.0);
+        assert_eq!(glyphs[5].x + glyphs[5].width, 100.0);
+    }
+
+    #[test]
+    fn test_group_words() {
+        let elements = parse_markup("<timing:0,5>ab cd<#ff0000>e\\nf").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let words = group_words(glyphs);
+        let summary: Vec<_> = words
+            .iter()
+            .map(|w| (w.text.as_str(), w.x, w.width, w.frame_offset(None)))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("ab", 100.0, 22.0, Some(0)),
+                ("cd", 136.0, 22.0, Some(5)),
+                ("e", 160.0, 10.0, Some(5)),
+                ("f", 100.0, 10.0, Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sizes_are_clamped() {
+        let elements = parse_markup("a<s200>b<s><s*0.1>c").unwrap();
+        let params = LayoutParams {
+            min_size: Some(5.0),
+            max_size: Some(40.0),
+            ..params()
+        };
+        let glyphs = place_glyphs(&elements, &params, &parse_alignment("左寄せ[上]"));
+        let sizes: Vec<_> = glyphs.iter().map(|g| g.size).collect();
+        assert_eq!(sizes, [10.0, 40.0, 5.0]);
+        assert_eq!(glyphs[2].x, 154.0);
+    }
+
+    #[test]
+    fn test_full_width_digits_advance_full_width() {
+        let elements = parse_markup("１２<s20>３").unwrap();
+        let boxes = glyph_boxes(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let xs: Vec<_> = boxes
+            .iter()
+            .map(|b| (b.text.as_str(), b.x, b.width))
+            .collect();
+        assert_eq!(
+            xs,
+            [
+                ("１", 100.0, 10.0),
+                ("２", 112.0, 10.0),
+                ("３", 124.0, 20.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timing_list_frame_offsets() {
+        let elements = parse_markup("a<timing:0,3,5>bc\\nde<timing>f").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let held: Vec<_> = glyphs.iter().map(|g| g.frame_offset(None)).collect();
+        assert_eq!(held, [None, Some(0), Some(3), Some(5), Some(5), None]);
+        let defaulted: Vec<_> = glyphs.iter().map(|g| g.frame_offset(Some(20))).collect();
+        assert_eq!(defaulted, [None, Some(0), Some(3), Some(5), Some(20), None]);
+    }
+
+    struct NarrowI;
+
+    impl GlyphMetrics for NarrowI {
+        fn advance(&self, c: char, size: f32) -> f32 {
+            if c == 'i' { size * 0.5 } else { size }
+        }
+    }
+
+    #[test]
+    fn test_place_glyphs_with_metrics_uses_advances() {
+        let elements = parse_markup("wiw").unwrap();
+        let glyphs = place_glyphs_with(
+            &elements,
+            &params(),
+            &parse_alignment("右寄せ[上]"),
+            &NarrowI,
+        );
+        let xs: Vec<_> = glyphs.iter().map(|g| (g.x, g.width)).collect();
+        assert_eq!(xs, [(71.0, 10.0), (83.0, 5.0), (90.0, 10.0)]);
+    }
+
+    #[test]
+    fn test_fit_auto_sizes_fills_target_width() {
+        let elements = parse_markup("a<sauto>bc\\n<s>d<sauto>e\\nf").unwrap();
+        let fitted = fit_auto_sizes(&elements, &params(), 100.0, &Approximate);
+        let sizes: Vec<_> = fitted.iter().map(|el| el.size).collect();
+        assert_eq!(
+            sizes,
+            [
+                None,
+                Some(SizeSpec::Absolute(43.0)),
+                Some(SizeSpec::Absolute(43.0)),
+                None,
+                Some(SizeSpec::Absolute(88.0)),
+                Some(SizeSpec::Absolute(88.0)),
+                Some(SizeSpec::Absolute(100.0)),
+            ]
+        );
+        let widths = line_widths(&fitted[..5], &params(), &Approximate);
+        assert_eq!(widths[..2], [100.0, 100.0]);
+    }
+
+    #[test]
+    fn test_layout_to_svg() {
+        let elements = parse_markup("a<#ff0000><s20,\"A&B\",B>\\<\\nc").unwrap();
+        let svg = layout_to_svg(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        assert!(svg.starts_with(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"100.00 50.00 32.00 34.00\">\n"
+        ));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<text ").count(), 3);
+        assert_eq!(svg.matches("</text>").count(), 3);
+        assert!(svg.contains(
+            "<text x=\"112.00\" y=\"50.00\" font-size=\"20.00\" dominant-baseline=\"text-before-edge\" fill=\"#ff0000\" font-family=\"A&amp;B\" font-weight=\"bold\">&lt;</text>"
+        ));
+    }
+
+    #[test]
+    fn test_reverse_lines_keeps_positions() {
+        let elements = parse_markup("ab<s20>c\\nde").unwrap();
+        let mut glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        reverse_lines(&mut glyphs);
+        let placed: Vec<_> = glyphs
+            .iter()
+            .map(|g| (g.text.as_str(), g.x, g.y, g.size))
+            .collect();
+        assert_eq!(
+            placed,
+            [
+                ("c", 100.0, 50.0, 20.0),
+                ("b", 112.0, 50.0, 10.0),
+                ("a", 124.0, 50.0, 10.0),
+                ("e", 100.0, 64.0, 20.0),
+                ("d", 122.0, 64.0, 20.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trim_lines_keeps_centered_lines_aligned() {
+        let elements = parse_markup("ab  \\n<#ff0000>a b\\t").unwrap();
+        let alignment = parse_alignment("中央揃え[上]");
+        let untrimmed = place_glyphs(&elements, &params(), &alignment);
+        assert_eq!(untrimmed[0].x, 77.0);
+
+        let trimmed = trim_lines(&elements);
+        let texts: Vec<_> = trimmed.iter().map(|el| el.text.as_str()).collect();
+        assert_eq!(texts, ["ab", "\n", "a b"]);
+        let glyphs = place_glyphs(&trimmed, &params(), &alignment);
+        assert_eq!((glyphs[0].x, glyphs[1].x), (89.0, 101.0));
+        assert_eq!(glyphs[2].x, 83.0);
+    }
+
+    #[test]
+    fn test_group_chunks() {
+        let elements = parse_markup("abcdefghij\\nxy").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let chunks = group_chunks(glyphs, 5);
+        let texts: Vec<_> = chunks.iter().map(|c| (c.text.as_str(), c.x)).collect();
+        assert_eq!(
+            texts,
+            [
+                ("ab", 100.0),
+                ("cd", 124.0),
+                ("ef", 148.0),
+                ("gh", 172.0),
+                ("ij", 196.0),
+                ("x", 100.0),
+                ("y", 112.0),
+            ]
+        );
+        assert_eq!(chunks[0].width, 22.0);
+
+        let elements = parse_markup("abc<#ff0000>d").unwrap();
+        let glyphs = place_glyphs(&elements, &params(), &parse_alignment("左寄せ[上]"));
+        let texts: Vec<_> = group_chunks(glyphs, 2)
+            .into_iter()
+            .map(|c| c.text)
+            .collect();
+        assert_eq!(texts, ["ab", "c", "d"]);
+    }
+
+    #[test]
+    fn test_trailing_newline_adds_no_height() {
+        let alignment = parse_alignment("中央揃え[中]");
+        let plain = parse_markup("a").unwrap();
+        let trailing = parse_markup("a\\n").unwrap();
+        assert_eq!(measure_text(&plain, &params()), (10.0, 10.0));
+        assert_eq!(measure_text(&trailing, &params()), (10.0, 10.0));
+        let y = |elements| place_glyphs(elements, &params(), &alignment)[0].y;
+        assert_eq!(y(&plain), 45.0);
+        assert_eq!(y(&trailing), y(&plain));
+
+        let leading = parse_markup("\\na").unwrap();
+        assert_eq!(measure_text(&leading, &params()), (10.0, 14.0));
+    }
+
+    #[test]
+    fn test_line_height_multiple_of_tallest_glyph() {
+        let elements = parse_markup("<s20>a\\nb").unwrap();
+        let alignment = parse_alignment("左寄せ[上]");
+        let absolute = place_glyphs(&elements, &params(), &alignment);
+        assert_eq!((absolute[1].x, absolute[1].y), (100.0, 64.0));
+
+        let params = LayoutParams {
+            line_height: Some(1.5),
+            ..params()
+        };
+        let multiple = place_glyphs(&elements, &params, &alignment);
+        assert_eq!((multiple[1].x, multiple[1].y), (100.0, 80.0));
+        assert_eq!(measure_text(&elements, &params), (20.0, 50.0));
+    }
+}
@@ -1,18 +1,58 @@
-mod parser;
+pub mod parser;
 use parser::{
-    alignment::{HDir, VDir, parse_alignment},
-    markup::parse_markup,
+    alignment::{TextAlignment, parse_alignment},
+    markup::{elements_to_pretty_json, parse_markup_with},
 };
 
+pub mod layout;
+
+mod color;
+
+pub mod metrics;
+
 mod entry;
-use entry::TEXT_ALIAS_TEMPLATE;
+use entry::{GROUP_EFFECT, is_group_control, is_split_glyph};
+
+pub mod config;
+use config::SplitConfig;
+
+pub mod error;
+use error::SplitError;
+
+pub mod params;
+use params::{
+    DRAW_EFFECT, DrawParams, TEXT_EFFECT, TextParams, TextSource, find_text, find_time_effect,
+    require,
+};
 
-use aviutl2::{
-    AnyResult, anyhow,
-    generic::{EditSection, GenericPlugin},
-    log,
+mod relayout;
+use relayout::{GlyphObject, relayout};
+
+pub mod placement;
+use placement::{LayerCursor, ProbeMemory, place_incremental};
+
+mod selection;
+use selection::{Selected, check_selection, collect_selection};
+
+mod timeline;
+use timeline::Timeline;
+
+pub mod timing;
+use timing::Timing;
+
+pub mod split;
+
+mod export;
+use export::{creation_infos_to_csv, to_object_file};
+use split::{
+    CreationInfo, SourceMode, SourcePlan, SplitOptions, SplitParams, SplitUnit,
+    compute_creation_infos, compute_merged_creation_infos, count_glyphs, measure_source,
+    stack_blocks,
 };
 
+use aviutl2::{AnyResult, generic::GenericPlugin, log};
+use std::path::PathBuf;
+
 #[aviutl2::plugin(GenericPlugin)]
 struct TextSplit {}
 
@@ -21,6 +61,7 @@ impl GenericPlugin for TextSplit {
         aviutl2::logger::LogBuilder::new()
             .filter_level(log::LevelFilter::Info)
             .try_init()?;
+        config::init(SplitConfig::load());
         Ok(TextSplit {})
     }
 
@@ -34,173 +75,491 @@ impl GenericPlugin for TextSplit {
 impl TextSplit {
     #[object(name = "テキストを分割")]
     fn split_text(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
-        let selected_objects = edit_section.get_selected_objects()?;
-        let mut creation_infos = Vec::new();
-        let mut objects_to_delete = Vec::new();
-
-        // Phase 1: Read all data from objects without mutation.
-        for obj_handle in &selected_objects {
-            let obj = edit_section.object(obj_handle);
-
-            let text_result = obj.get_effect_item("テキスト", 0, "テキスト");
-            let text = match text_result {
-                Ok(t) => t,
-                Err(_) => {
-                    continue;
-                }
-            };
-
-            let elements = parse_markup(&text)
-                .map_err(|e| anyhow::anyhow!("テキストの解析に失敗しました: {}: {}", text, e))?;
-
-            let layer_frame = obj.get_layer_frame()?;
-            let _layer = layer_frame.layer;
-            let start = layer_frame.start;
-            let end = layer_frame.end;
-
-            let _size: f32 = obj.get_effect_item("テキスト", 0, "サイズ")?.parse()?;
-            let kern: f32 = obj.get_effect_item("テキスト", 0, "字間")?.parse()?;
-            let lnsp: f32 = obj.get_effect_item("テキスト", 0, "行間")?.parse()?;
-            let font = obj.get_effect_item("テキスト", 0, "フォント")?;
-            let color = obj.get_effect_item("テキスト", 0, "文字色")?;
-            let subcolor = obj.get_effect_item("テキスト", 0, "影・縁色")?;
-            let style = obj.get_effect_item("テキスト", 0, "文字装飾")?;
-            let bold = obj.get_effect_item("テキスト", 0, "B")?;
-            let italic = obj.get_effect_item("テキスト", 0, "I")?;
-
-            let _x: f32 = obj
-                .get_effect_item("標準描画", 0, "X")
-                .unwrap_or("0.0".to_string())
-                .parse()?;
-            let _y: f32 = obj
-                .get_effect_item("標準描画", 0, "Y")
-                .unwrap_or("0.0".to_string())
-                .parse()?;
-            let z: f32 = obj
-                .get_effect_item("標準描画", 0, "Z")
-                .unwrap_or("0.0".to_string())
-                .parse()?;
-            let alpha = obj.get_effect_item("標準描画", 0, "透明度")?;
-            let blend = obj.get_effect_item("標準描画", 0, "合成モード")?;
-
-            let alignment = obj
-                .get_effect_item("テキスト", 0, "文字揃え")
-                .map(|align| parse_alignment(&align))?;
-
-            let mut w: f32 = 0.0;
-            let mut w_temp: f32 = 0.0;
-            let mut h: f32 = 0.0;
-            let mut h_temp: f32 = 0.0;
-            for el in elements.clone() {
-                if el.text == "\\n" {
-                    w = w.max(w_temp);
-                    h += h_temp + lnsp;
-                    w_temp = 0.0;
-                    h_temp = 0.0;
-                    continue;
-                }
-                let size = el.size.unwrap_or(_size);
-                w_temp += size + kern;
-                h_temp = h_temp.max(size);
-            }
-
-            match alignment.hdir {
-                HDir::Left => {
-                    w = 0.0;
-                }
-                HDir::Mid => {
-                    w *= 0.5;
-                }
-                HDir::Right => (),
-            }
-
-            match alignment.vdir {
-                VDir::Top => {
-                    h = 0.0;
-                }
-                VDir::Center => {
-                    h *= 0.5;
-                }
-                VDir::Bottom => (),
-            }
-
-            let mut x = _x - w;
-            let mut y = _y - h;
-
-            let mut layer = _layer + 1;
-
-            for el in elements.clone() {
-                if el.text == "\\n" {
-                    x = _x - w;
-                    y += _size + lnsp;
-                    continue;
-                }
-                for c in el.text.chars() {
-                    let size = el.size.unwrap_or(_size);
-                    let alias = TEXT_ALIAS_TEMPLATE
-                        .replace("{start}", &start.to_string())
-                        .replace("{end}", &end.to_string())
-                        .replace("{size}", &format!("{:.2}", size))
-                        .replace("{font}", &el.font.as_ref().unwrap_or(&font))
-                        .replace("{color}", &el.color.as_ref().unwrap_or(&color))
-                        .replace("{subcolor}", &subcolor.to_string())
-                        .replace("{style}", &style.to_string())
-                        .replace(
-                            "{bold}",
-                            if let Some(is_bold) = el.is_bold {
-                                if is_bold { "1" } else { "0" }
-                            } else {
-                                &bold
-                            },
-                        )
-                        .replace(
-                            "{italic}",
-                            if let Some(is_italic) = el.is_italic {
-                                if is_italic { "1" } else { "0" }
-                            } else {
-                                &italic
-                            },
-                        )
-                        .replace("{text}", &c.to_string())
-                        .replace("{ox}", &format!("{:.2}", x))
-                        .replace("{oy}", &format!("{:.2}", y))
-                        .replace("{oz}", &format!("{:.2}", z))
-                        .replace("{alpha}", &alpha)
-                        .replace("{blend}", &blend);
-
-                    creation_infos.push((alias, layer, start, end - start));
-
-                    x += size + kern;
-                    layer += 1;
-                }
-            }
-
-            objects_to_delete.push(obj_handle.clone());
+        split_objects(edit_section, SplitOptions::default(), config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（タイプライター）")]
+    fn split_text_typewriter(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let step = config::get().typewriter_step;
+        let options = SplitOptions {
+            timing: Timing::Typewriter { step },
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを単語ごとに分割（カラオケ）")]
+    fn split_text_karaoke(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            unit: SplitUnit::Word,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（行を等分）")]
+    fn split_text_chunks(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            unit: SplitUnit::Chunks(config::get().chunk_count),
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（指定の文字数ずつ）")]
+    fn split_text_fixed_chunks(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            unit: SplitUnit::FixedChunks(config::get().chunk_size),
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（元を非表示で残す）")]
+    fn split_text_keep_reference(
+        edit_section: &mut aviutl2::generic::EditSection,
+    ) -> AnyResult<()> {
+        let options = SplitOptions {
+            source: SourceMode::KeepHidden,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（移動を保持）")]
+    fn split_text_keep_motion(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            keep_motion: true,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（グループにまとめる）")]
+    fn split_text_grouped(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            group: true,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（縁取りを重ねる）")]
+    fn split_text_stroke(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            stroke: true,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "テキストを分割（選択をつなげる）")]
+    fn split_text_merged(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let options = SplitOptions {
+            merge: true,
+            ..SplitOptions::default()
+        };
+        split_objects(edit_section, options, config::get())?;
+        Ok(())
+    }
+
+    #[object(name = "分割した文字を並べ直す")]
+    fn relayout_glyphs(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        Ok(relayout_objects(edit_section, config::get())?)
+    }
+
+    #[object(name = "分割結果をエイリアスに書き出す")]
+    fn export_split_alias(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let path = config::get().export_path();
+        Ok(export_objects(
+            edit_section,
+            SplitOptions::default(),
+            to_object_file,
+            path,
+        )?)
+    }
+
+    #[object(name = "分割結果を CSV に書き出す")]
+    fn export_split_csv(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        let path = config::get().export_path().with_extension("csv");
+        Ok(export_objects(
+            edit_section,
+            SplitOptions::default(),
+            creation_infos_to_csv,
+            path,
+        )?)
+    }
+
+    #[object(name = "テキストの大きさを表示")]
+    fn show_text_extent(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        Ok(log_text_extents(edit_section)?)
+    }
+
+    #[object(name = "分割後のオブジェクト数を表示")]
+    fn show_object_count(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        Ok(log_object_counts(edit_section)?)
+    }
+
+    #[object(name = "解析結果を表示")]
+    fn show_parse_result(edit_section: &mut aviutl2::generic::EditSection) -> AnyResult<()> {
+        Ok(log_parse_results(edit_section)?)
+    }
+}
+
+/// Finds the text of `object` in the configured effects, or in its name when
+/// `name_fallback` is on.
+fn find_object_text<'a, T: Timeline>(
+    timeline: &T,
+    object: &T::Handle,
+    config: &'a SplitConfig,
+) -> Option<(TextSource<'a>, String)> {
+    let read = |effect: &str, index, item: &str| timeline.effect_item(object, effect, index, item);
+    let name = || timeline.name(object).filter(|_| config.name_fallback);
+    find_text(&config.text_effects, read, name)
+}
+
+/// Reads every selected text object and plans its glyphs without touching
+/// the timeline.
+fn plan_objects<T: Timeline>(
+    timeline: &T,
+    options: &SplitOptions,
+    config: &SplitConfig,
+) -> Result<(Vec<CreationInfo>, SourcePlan<T::Handle>), SplitError> {
+    let selected_objects = collect_selection(timeline)?;
+
+    let mut texts = Vec::new();
+    let mut sources = SourcePlan::default();
+    for selected in &selected_objects {
+        if let Some(read) = read_object(timeline, selected, config, &mut sources.warnings)? {
+            texts.push(read);
+            sources.push(selected.handle.clone(), options.source);
         }
+    }
 
-        // Phase 2: Mutate the timeline.
-        for (alias, layer, start, length) in creation_infos {
-            create_object_from_alias_incremental(edit_section, &alias, layer, start, length);
+    let mut blocks = Vec::new();
+    if options.merge {
+        if let Some((params, alignment)) = merged_params(&texts) {
+            let texts: Vec<&str> = texts.iter().map(|(text, ..)| text.as_str()).collect();
+            blocks.push(compute_merged_creation_infos(
+                &texts, &params, &alignment, options, config,
+            )?);
+        }
+    } else {
+        for (text, params, alignment) in &texts {
+            blocks.push(compute_creation_infos(
+                text, params, alignment, options, config,
+            )?);
         }
+    }
 
-        for obj_idx in objects_to_delete {
-            edit_section.object(&obj_idx).delete_object()?;
+    let creation_infos = match selected_objects.iter().map(|s| s.layer).max() {
+        Some(top) if config.above_selection => stack_blocks(blocks, top + 1),
+        _ => blocks.concat(),
+    };
+    Ok((creation_infos, sources))
+}
+
+/// Parameters and alignment for splitting the texts as one over the frames
+/// of all the objects, so the glyphs share a single alignment and layer
+/// block. The first object's are used for all of them.
+fn merged_params(
+    texts: &[(String, SplitParams, TextAlignment)],
+) -> Option<(SplitParams, TextAlignment)> {
+    let ((_, first, first_alignment), rest) = texts.split_first()?;
+    let mut merged = first.clone();
+    for (_, params, alignment) in rest {
+        if params.text != first.text || alignment != first_alignment {
+            log::warn!(
+                "文字の設定や文字揃えが最初のオブジェクトと異なるため、最初のオブジェクトに合わせます"
+            );
         }
+        merged.start = merged.start.min(params.start);
+        merged.end = merged.end.max(params.end);
+    }
+    Some((merged, first_alignment.clone()))
+}
 
-        Ok(())
+/// Reads the text, parameters and alignment of one selected object; `None`
+/// when it is skipped. Warnings about the object are logged and added to
+/// `warnings`.
+fn read_object<T: Timeline>(
+    timeline: &T,
+    selected: &Selected<T::Handle>,
+    config: &SplitConfig,
+    warnings: &mut Vec<String>,
+) -> Result<Option<(String, SplitParams, TextAlignment)>, SplitError> {
+    let obj_handle = &selected.handle;
+
+    // Glyphs would get no frames, which the host may reject or not show.
+    if selected.end <= selected.start {
+        log::warn!("長さが 0 のオブジェクトのためスキップしました");
+        return Ok(None);
+    }
+
+    if !config.matches_name(timeline.name(obj_handle).as_deref()) {
+        log::info!("名前が name_filter に一致しないためスキップしました");
+        return Ok(None);
+    }
+
+    if timeline
+        .alias(obj_handle)
+        .is_some_and(|alias| is_split_glyph(&alias))
+    {
+        log::info!("分割済みのオブジェクトのためスキップしました");
+        return Ok(None);
+    }
+
+    let Some((source, text)) = find_object_text(timeline, obj_handle, config) else {
+        return Ok(None);
+    };
+    if let Some(effect) = find_time_effect(|effect| timeline.has_effect(obj_handle, effect)) {
+        let warning = format!(
+            "{} が付いているため、分割後の文字の表示タイミングが元と異なる場合があります",
+            effect
+        );
+        log::warn!("{}", warning);
+        warnings.push(warning);
+    }
+
+    let read =
+        |effect: &str, index, item: &str| timeline.effect_item(obj_handle, effect, index, item);
+    let params = SplitParams {
+        text: TextParams::read_source(source, read)?.spacing_to_pixels(config.spacing_ratio_range),
+        draw: DrawParams::read(|item| read(DRAW_EFFECT, 0, item))?,
+        layer: selected.layer,
+        start: selected.start,
+        end: selected.end,
+    };
+    let alignment = match source {
+        TextSource::Effect(effect, index) => parse_alignment(&require(
+            |item| read(effect, index, item),
+            effect,
+            "文字揃え",
+        )?),
+        TextSource::Name => TextAlignment::default(),
+    };
+    log::debug!("文字揃え: {}", alignment);
+    Ok(Some((text, params, alignment)))
+}
+
+/// Splits every selected text object, returning the warnings logged about
+/// the sources.
+fn split_objects<T: Timeline>(
+    timeline: &mut T,
+    options: SplitOptions,
+    config: &SplitConfig,
+) -> Result<Vec<String>, SplitError> {
+    // Phase 1: Read all data from objects without mutation.
+    let (creation_infos, sources) = plan_objects(timeline, &options, config)?;
+
+    // Phase 2: Mutate the timeline.
+    let mut cursor = LayerCursor::default();
+    let mut memory = ProbeMemory::default();
+    // The open group control: its handle, its layer and the highest layer
+    // of its glyphs.
+    let mut group = None;
+    for (alias, layer, start, length) in creation_infos {
+        let target = cursor.target(layer);
+        let mut handle = None;
+        let placed = place_incremental(
+            target,
+            (start, length),
+            &mut memory,
+            config.max_probe_attempts,
+            &mut |layer| {
+                timeline
+                    .create_object(&alias, layer, start, length)
+                    .map(|created| handle = Some(created))
+            },
+        )?;
+        cursor.placed(target, placed);
+        if is_group_control(&alias) {
+            close_group(timeline, group.take())?;
+            group = handle.map(|handle| (handle, placed, placed));
+        } else if let Some((_, _, last)) = &mut group {
+            *last = placed.max(*last);
+        }
+    }
+    close_group(timeline, group)?;
+
+    for obj_idx in sources.to_delete {
+        timeline.delete_object(&obj_idx)?;
+    }
+
+    for obj_idx in sources.to_hide {
+        hide_source(timeline, &obj_idx, config.reference_layer)?;
+    }
+
+    Ok(sources.warnings)
+}
+
+/// Sets how many layers a group control covers from where its glyphs were
+/// actually placed, as collisions may have pushed them past the planned
+/// count.
+fn close_group<T: Timeline>(
+    timeline: &mut T,
+    group: Option<(T::Handle, usize, usize)>,
+) -> Result<(), SplitError> {
+    let Some((handle, layer, last)) = group else {
+        return Ok(());
+    };
+    let layers = (last - layer).to_string();
+    timeline.set_effect_item(&handle, GROUP_EFFECT, 0, "対象レイヤー数", &layers)
+}
+
+/// Moves the selected glyph objects of an earlier split to where a layout
+/// with `relayout_kern` and `relayout_lnsp` puts them, without recreating
+/// them. Their order on the timeline is their reading order.
+fn relayout_objects<T: Timeline>(timeline: &mut T, config: &SplitConfig) -> Result<(), SplitError> {
+    let selected_objects = collect_selection(timeline)?;
+
+    let mut handles = Vec::new();
+    let mut glyphs = Vec::new();
+    for selected in selected_objects {
+        let handle = selected.handle;
+        if !timeline
+            .alias(&handle)
+            .is_some_and(|alias| is_split_glyph(&alias))
+        {
+            continue;
+        }
+        let read = |effect: &str, item: &str| timeline.effect_item(&handle, effect, 0, item);
+        if let Some(glyph) = GlyphObject::read(read) {
+            handles.push(handle);
+            glyphs.push(glyph);
+        }
+    }
+    let Some(first) = glyphs.first() else {
+        log::warn!("分割した文字が選択されていません");
+        return Ok(());
+    };
+
+    let metrics = metrics::metrics_for(&first.font, &config.font_files);
+    let positions = relayout(
+        &glyphs,
+        config.relayout_kern,
+        config.relayout_lnsp,
+        config.glyph_origin,
+        metrics.as_ref(),
+    );
+    for (handle, (x, y)) in handles.iter().zip(positions) {
+        timeline.set_effect_item(handle, DRAW_EFFECT, 0, "X", &format!("{:.2}", x))?;
+        timeline.set_effect_item(handle, DRAW_EFFECT, 0, "Y", &format!("{:.2}", y))?;
+        if let Some(kern) = config.relayout_kern {
+            timeline.set_effect_item(handle, TEXT_EFFECT, 0, "字間", &format!("{:.2}", kern))?;
+        }
+    }
+    log::info!("{} 個の文字を並べ直しました", handles.len());
+    Ok(())
+}
+
+/// Writes the planned glyphs to `path` as `render` formats them instead of
+/// to the timeline.
+fn export_objects<T: Timeline>(
+    timeline: &T,
+    options: SplitOptions,
+    render: fn(&[CreationInfo]) -> String,
+    path: PathBuf,
+) -> Result<(), SplitError> {
+    let (creation_infos, _) = plan_objects(timeline, &options, config::get())?;
+    std::fs::write(&path, render(&creation_infos)).map_err(|e| SplitError::Export {
+        path: path.display().to_string(),
+        message: e.to_string(),
+    })?;
+    log::info!(
+        "{} 個のオブジェクトを {} に書き出しました",
+        creation_infos.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Logs the parsed markup of every selected object without touching the
+/// timeline.
+fn log_parse_results<T: Timeline>(timeline: &T) -> Result<(), SplitError> {
+    let config = config::get();
+    let selected = timeline.selected_objects()?;
+    check_selection(&selected)?;
+
+    for handle in selected {
+        let Some((_, text)) = find_object_text(timeline, &handle, config) else {
+            log::warn!("テキストを持たないオブジェクトのためスキップしました");
+            continue;
+        };
+        match parse_markup_with(&text, &config.parse_options()) {
+            Ok(elements) => match elements_to_pretty_json(&elements) {
+                Ok(json) => log::info!("{}", json),
+                Err(e) => log::error!("解析結果を JSON にできませんでした: {}", e),
+            },
+            Err(message) => log::error!("{}", SplitError::Parse { text, message }),
+        }
+    }
+    Ok(())
+}
+
+/// Logs the width and height every selected text object's glyphs would
+/// cover, without splitting.
+fn log_text_extents<T: Timeline>(timeline: &T) -> Result<(), SplitError> {
+    let config = config::get();
+    let selected = timeline.selected_objects()?;
+    check_selection(&selected)?;
+
+    for handle in selected {
+        let Some((source, text)) = find_object_text(timeline, &handle, config) else {
+            log::warn!("テキストを持たないオブジェクトのためスキップしました");
+            continue;
+        };
+        let params = TextParams::read_source(source, |effect, index, item| {
+            timeline.effect_item(&handle, effect, index, item)
+        })?
+        .spacing_to_pixels(config.spacing_ratio_range);
+        let (width, height) = measure_source(&text, &params, config)?;
+        log::info!("幅 {:.2} × 高さ {:.2}", width, height);
+    }
+    Ok(())
+}
+
+/// Logs how many objects splitting every selected text object would
+/// create with the plain split, without splitting.
+fn log_object_counts<T: Timeline>(timeline: &T) -> Result<(), SplitError> {
+    let config = config::get();
+    let options = SplitOptions::default();
+    let selected_objects = collect_selection(timeline)?;
+
+    for selected in &selected_objects {
+        let Some((text, params, alignment)) =
+            read_object(timeline, selected, config, &mut Vec::new())?
+        else {
+            continue;
+        };
+        let count = count_glyphs(&text, &params, &alignment, &options, config)?;
+        log::info!("{} 個のオブジェクトが作成されます", count);
     }
+    Ok(())
 }
 
-fn create_object_from_alias_incremental(
-    edit_section: &mut EditSection,
-    alias: &str,
-    layer: usize,
-    frame: usize,
-    length: usize,
-) {
-    if let Err(_) = edit_section.create_object_from_alias(alias, layer, frame, length) {
-        create_object_from_alias_incremental(edit_section, alias, layer + 1, frame, length);
+/// Makes a kept source invisible and optionally moves it to the reference
+/// layer. Fully transparent rather than deleted, so its text stays editable.
+fn hide_source<T: Timeline>(
+    timeline: &mut T,
+    handle: &T::Handle,
+    reference_layer: Option<usize>,
+) -> Result<(), SplitError> {
+    timeline.set_effect_item(handle, DRAW_EFFECT, 0, "透明度", "100.00")?;
+    if let Some(layer) = reference_layer {
+        let (_, start, _) = timeline.layer_frame(handle)?;
+        timeline.move_object(handle, layer, start)?;
     }
+    Ok(())
 }
 
 aviutl2::register_generic_plugin!(TextSplit);
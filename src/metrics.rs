@@ -0,0 +1,212 @@
+/// Advance widths the layout steps through the text with.
+pub trait GlyphMetrics {
+    /// Horizontal advance of `c` at font size `size`, before kern.
+    fn advance(&self, c: char, size: f32) -> f32;
+
+    /// Contours of `c` at font size `size`, in pixels from the glyph's
+    /// top-left; `None` when there is no font or the glyph has no contours.
+    #[cfg(feature = "glyph-paths")]
+    fn outline(&self, _c: char, _size: f32) -> Option<Vec<PathSegment>> {
+        None
+    }
+}
+
+/// One drawing command of a glyph outline.
+#[cfg(feature = "glyph-paths")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathSegment {
+    Move((f32, f32)),
+    Line((f32, f32)),
+    /// Control point, then end point.
+    Quad((f32, f32), (f32, f32)),
+    /// Two control points, then end point.
+    Cubic((f32, f32), (f32, f32), (f32, f32)),
+    Close,
+}
+
+/// The `size + kern` model: every glyph is treated as a square of the font
+/// size.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Approximate;
+
+impl GlyphMetrics for Approximate {
+    fn advance(&self, _c: char, size: f32) -> f32 {
+        size
+    }
+}
+
+/// Steps every glyph by the same width whatever its size, for monospaced
+/// output. Outlines still come from `inner`.
+pub struct Monospace {
+    pub width: f32,
+    pub inner: Box<dyn GlyphMetrics>,
+}
+
+impl GlyphMetrics for Monospace {
+    fn advance(&self, _c: char, _size: f32) -> f32 {
+        self.width
+    }
+
+    #[cfg(feature = "glyph-paths")]
+    fn outline(&self, c: char, size: f32) -> Option<Vec<PathSegment>> {
+        self.inner.outline(c, size)
+    }
+}
+
+/// Real advance widths read from a font file.
+#[cfg(feature = "font-metrics")]
+pub struct FontMetrics {
+    font: ab_glyph::FontVec,
+    units_per_em: f32,
+}
+
+#[cfg(feature = "font-metrics")]
+impl FontMetrics {
+    /// Loads the font at `path`; `None` when it is missing or unreadable.
+    pub fn load(path: &std::path::Path) -> Option<FontMetrics> {
+        use ab_glyph::Font;
+
+        let data = std::fs::read(path).ok()?;
+        let font = ab_glyph::FontVec::try_from_vec(data).ok()?;
+        let units_per_em = font.units_per_em()?;
+        Some(FontMetrics { font, units_per_em })
+    }
+}
+
+#[cfg(feature = "font-metrics")]
+impl GlyphMetrics for FontMetrics {
+    fn advance(&self, c: char, size: f32) -> f32 {
+        use ab_glyph::Font;
+
+        let unscaled = self.font.h_advance_unscaled(self.font.glyph_id(c));
+        unscaled / self.units_per_em * size
+    }
+
+    #[cfg(feature = "glyph-paths")]
+    fn outline(&self, c: char, size: f32) -> Option<Vec<PathSegment>> {
+        use ab_glyph::{Font, OutlineCurve, Point};
+
+        // Font units grow upwards from the baseline; the glyph's top is at
+        // its ascent.
+        let scale = size / self.units_per_em;
+        let ascent = self.font.ascent_unscaled();
+        let px = |p: Point| (p.x * scale, (ascent - p.y) * scale);
+
+        let outline = self.font.outline(self.font.glyph_id(c))?;
+        let mut segments = Vec::new();
+        let mut last: Option<Point> = None;
+        for curve in &outline.curves {
+            let (from, to, segment) = match *curve {
+                OutlineCurve::Line(from, to) => (from, to, PathSegment::Line(px(to))),
+                OutlineCurve::Quad(from, ctrl, to) => {
+                    (from, to, PathSegment::Quad(px(ctrl), px(to)))
+                }
+                OutlineCurve::Cubic(from, c1, c2, to) => {
+                    (from, to, PathSegment::Cubic(px(c1), px(c2), px(to)))
+                }
+            };
+            // Curves don't mark contour ends; a jump starts a new contour.
+            if last != Some(from) {
+                if last.is_some() {
+                    segments.push(PathSegment::Close);
+                }
+                segments.push(PathSegment::Move(px(from)));
+            }
+            segments.push(segment);
+            last = Some(to);
+        }
+        if last.is_some() {
+            segments.push(PathSegment::Close);
+        }
+        (!segments.is_empty()).then_some(segments)
+    }
+}
+
+/// Picks the metrics for an object using `font`: the font file configured
+/// for it when the `font-metrics` feature is enabled and the file loads, the
+/// approximation otherwise. A fallback list such as `Meiryo,Arial` uses the
+/// first font that has a file configured.
+#[cfg_attr(not(feature = "font-metrics"), allow(unused_variables))]
+pub fn metrics_for(
+    font: &str,
+    font_files: &std::collections::HashMap<String, String>,
+) -> Box<dyn GlyphMetrics> {
+    #[cfg(feature = "font-metrics")]
+    if let Some(path) = font_names(font).find_map(|name| font_files.get(name)) {
+        match FontMetrics::load(std::path::Path::new(path)) {
+            Some(metrics) => return Box::new(metrics),
+            None => aviutl2::log::warn!("フォント {} を読み込めませんでした", path),
+        }
+    }
+    Box::new(Approximate)
+}
+
+/// Whether `font_files` has an existing file for one of the fonts in `font`.
+#[cfg(feature = "font-metrics")]
+pub fn has_font_file(font: &str, font_files: &std::collections::HashMap<String, String>) -> bool {
+    font_names(font)
+        .filter_map(|name| font_files.get(name))
+        .any(|path| std::path::Path::new(path).is_file())
+}
+
+/// The names in a font value, which may be a fallback list separated by `,`
+/// or `|`.
+pub fn font_names(font: &str) -> impl Iterator<Item = &str> {
+    font.split([',', '|'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+#[cfg(all(test, feature = "font-metrics"))]
+mod test {
+    use super::*;
+
+    /// A proportional font that ships with the platform.
+    const KNOWN_FONTS: &[&str] = &[
+        "C:/Windows/Fonts/arial.ttf",
+        "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+        "/usr/share/fonts/truetype/DejaVuSans.ttf",
+    ];
+
+    fn known_font() -> FontMetrics {
+        KNOWN_FONTS
+            .iter()
+            .find_map(|path| FontMetrics::load(std::path::Path::new(path)))
+            .expect("no known font found")
+    }
+
+    #[test]
+    fn test_font_advances_are_proportional() {
+        let metrics = known_font();
+        assert!(metrics.advance('i', 30.0) < metrics.advance('W', 30.0));
+        let ratio = metrics.advance('W', 60.0) / metrics.advance('W', 30.0);
+        assert!((ratio - 2.0).abs() < 1e-4);
+    }
+
+    #[cfg(feature = "glyph-paths")]
+    #[test]
+    fn test_outline_of_known_glyph() {
+        let segments = known_font().outline('A', 30.0).unwrap();
+        assert!(matches!(segments.first(), Some(PathSegment::Move(_))));
+        assert_eq!(segments.last(), Some(&PathSegment::Close));
+        assert!(segments.len() > 2);
+        assert_eq!(known_font().outline(' ', 30.0), None);
+    }
+
+    #[test]
+    fn test_font_list_uses_first_configured_font() {
+        let path = KNOWN_FONTS
+            .iter()
+            .find(|path| FontMetrics::load(std::path::Path::new(path)).is_some())
+            .unwrap();
+        let files = [("Arial".to_string(), path.to_string())].into();
+        let metrics = metrics_for("Meiryo, Arial", &files);
+        assert!(metrics.advance('i', 30.0) < metrics.advance('W', 30.0));
+    }
+
+    #[test]
+    fn test_unloadable_font_falls_back() {
+        let files = [("Missing".to_string(), "/nonexistent.ttf".to_string())].into();
+        assert_eq!(metrics_for("Missing", &files).advance('i', 30.0), 30.0);
+    }
+}
@@ -0,0 +1,386 @@
+use crate::error::SplitError;
+
+pub const TEXT_EFFECT: &str = "テキスト";
+pub const DRAW_EFFECT: &str = "標準描画";
+
+/// Reads a required item, reporting which one is missing.
+pub fn require(
+    read: impl Fn(&str) -> Option<String>,
+    effect: &str,
+    item: &str,
+) -> Result<String, SplitError> {
+    read(item).ok_or_else(|| SplitError::MissingItem {
+        effect: effect.to_string(),
+        item: item.to_string(),
+    })
+}
+
+/// Effects that change how an object's frames map to its time. Glyphs only
+/// copy the object's range, so they don't follow these.
+pub const TIME_EFFECTS: &[&str] = &["時間制御", "再生速度"];
+
+/// Returns the first of `TIME_EFFECTS` the object has.
+pub fn find_time_effect(has_effect: impl Fn(&str) -> bool) -> Option<&'static str> {
+    TIME_EFFECTS
+        .iter()
+        .copied()
+        .find(|effect| has_effect(effect))
+}
+
+/// How many instances of each effect `find_text_effect` scans.
+pub const MAX_EFFECT_INDEX: usize = 8;
+
+/// Finds the first effect in `effects` that has a テキスト item, returning
+/// its name and index along with the text. The index isn't assumed to be 0,
+/// so objects with preceding effects still split.
+pub fn find_text_effect(
+    effects: &[String],
+    read: impl Fn(&str, usize, &str) -> Option<String>,
+) -> Option<(&str, usize, String)> {
+    effects.iter().find_map(|effect| {
+        (0..MAX_EFFECT_INDEX).find_map(|index| {
+            read(effect, index, "テキスト").map(|text| (effect.as_str(), index, text))
+        })
+    })
+}
+
+/// Where an object's text was read from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextSource<'a> {
+    /// The テキスト item of the effect at this index.
+    Effect(&'a str, usize),
+    /// The object's name, for objects that keep their text there.
+    Name,
+}
+
+/// Like `find_text_effect`, falling back to `name` when no effect has text.
+/// An empty name counts as no text.
+pub fn find_text<'a>(
+    effects: &'a [String],
+    read: impl Fn(&str, usize, &str) -> Option<String>,
+    name: impl FnOnce() -> Option<String>,
+) -> Option<(TextSource<'a>, String)> {
+    match find_text_effect(effects, read) {
+        Some((effect, index, text)) => Some((TextSource::Effect(effect, index), text)),
+        None => name()
+            .filter(|name| !name.is_empty())
+            .map(|name| (TextSource::Name, name)),
+    }
+}
+
+/// Returns the value at the start of an animated item such as
+/// `0.00,120.00,直線移動`, or the item itself when it isn't animated.
+pub fn track_start(value: &str) -> &str {
+    value.split(',').next().unwrap_or(value).trim()
+}
+
+fn parse_number(value: String, effect: &str, item: &str) -> Result<f32, SplitError> {
+    value.parse().map_err(|_| SplitError::InvalidNumber {
+        effect: effect.to_string(),
+        item: item.to_string(),
+        value,
+    })
+}
+
+/// Values read from the object's text effect.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextParams {
+    pub size: f32,
+    pub kern: f32,
+    pub lnsp: f32,
+    pub font: String,
+    pub color: String,
+    pub subcolor: String,
+    pub decoration: String,
+    pub bold: String,
+    pub italic: String,
+}
+
+impl Default for TextParams {
+    /// The values of a newly created text object.
+    fn default() -> Self {
+        TextParams {
+            size: 34.0,
+            kern: 0.0,
+            lnsp: 0.0,
+            font: "Yu Gothic UI".to_string(),
+            color: "ffffff".to_string(),
+            subcolor: "000000".to_string(),
+            decoration: "標準文字".to_string(),
+            bold: "0".to_string(),
+            italic: "0".to_string(),
+        }
+    }
+}
+
+impl TextParams {
+    /// Reads the items of the effect the text came from, or the defaults
+    /// when it came from the object's name.
+    pub fn read_source(
+        source: TextSource,
+        read: impl Fn(&str, usize, &str) -> Option<String>,
+    ) -> Result<TextParams, SplitError> {
+        match source {
+            TextSource::Effect(effect, index) => {
+                TextParams::read(effect, |item| read(effect, index, item))
+            }
+            TextSource::Name => Ok(TextParams::default()),
+        }
+    }
+
+    /// Converts 字間 and 行間 values within `ratio_range` from multiples of
+    /// the size to pixels. Values outside it, or every value when unset,
+    /// are already pixels.
+    pub fn spacing_to_pixels(self, ratio_range: Option<(f32, f32)>) -> TextParams {
+        let Some((min, max)) = ratio_range else {
+            return self;
+        };
+        let pixels = |value: f32| {
+            if (min..=max).contains(&value) {
+                value * self.size
+            } else {
+                value
+            }
+        };
+        TextParams {
+            kern: pixels(self.kern),
+            lnsp: pixels(self.lnsp),
+            ..self
+        }
+    }
+
+    /// Reads the items of `effect` through `read`; all of them are required.
+    pub fn read(
+        effect: &str,
+        read: impl Fn(&str) -> Option<String>,
+    ) -> Result<TextParams, SplitError> {
+        let item = |name: &str| require(&read, effect, name);
+        let number = |name: &str| parse_number(item(name)?, effect, name);
+        Ok(TextParams {
+            size: number("サイズ")?,
+            kern: number("字間")?,
+            lnsp: number("行間")?,
+            font: item("フォント")?,
+            color: item("文字色")?,
+            subcolor: item("影・縁色")?,
+            decoration: item("文字装飾")?,
+            bold: item("B")?,
+            italic: item("I")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawParams {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub alpha: String,
+    pub blend: String,
+    /// Raw X and Y items when either is animated.
+    pub position_tracks: Option<(String, String)>,
+}
+
+impl Default for DrawParams {
+    fn default() -> Self {
+        DrawParams {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            alpha: "0.00".to_string(),
+            blend: "通常".to_string(),
+            position_tracks: None,
+        }
+    }
+}
+
+impl DrawParams {
+    /// Reads the 標準描画 items through `read`, keeping the default for any
+    /// item the object doesn't have (e.g. objects using a custom draw effect).
+    /// Animated items are read at the object's start.
+    pub fn read(read: impl Fn(&str) -> Option<String>) -> Result<DrawParams, SplitError> {
+        let default = DrawParams::default();
+        let coord = |item: &str, default: f32| match read(item) {
+            Some(value) => match track_start(&value).parse() {
+                Ok(number) => Ok(number),
+                Err(_) => parse_number(value, DRAW_EFFECT, item),
+            },
+            None => Ok(default),
+        };
+        let x_track = read("X").unwrap_or_else(|| "0.00".to_string());
+        let y_track = read("Y").unwrap_or_else(|| "0.00".to_string());
+        let position_tracks =
+            (x_track.contains(',') || y_track.contains(',')).then_some((x_track, y_track));
+        Ok(DrawParams {
+            x: coord("X", default.x)?,
+            y: coord("Y", default.y)?,
+            z: coord("Z", default.z)?,
+            alpha: read("透明度").unwrap_or(default.alpha),
+            blend: read("合成モード").unwrap_or(default.blend),
+            position_tracks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_without_draw_effect_uses_defaults() {
+        let params = DrawParams::read(|_| None).unwrap();
+        assert_eq!(params, DrawParams::default());
+    }
+
+    #[test]
+    fn test_read_partial_draw_effect() {
+        let params = DrawParams::read(|item| match item {
+            "X" => Some("12.5".to_string()),
+            "合成モード" => Some("加算".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(params.x, 12.5);
+        assert_eq!(params.y, 0.0);
+        assert_eq!(params.alpha, "0.00");
+        assert_eq!(params.blend, "加算");
+    }
+
+    #[test]
+    fn test_read_animated_position_at_start() {
+        let params = DrawParams::read(|item| match item {
+            "X" => Some("10.00,50.00,直線移動".to_string()),
+            "Y" => Some("-4.00".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!((params.x, params.y), (10.0, -4.0));
+        assert_eq!(
+            params.position_tracks,
+            Some(("10.00,50.00,直線移動".to_string(), "-4.00".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_read_rejects_malformed_coordinate() {
+        let result = DrawParams::read(|item| (item == "Y").then(|| "abc".to_string()));
+        assert_eq!(
+            result,
+            Err(SplitError::InvalidNumber {
+                effect: DRAW_EFFECT.to_string(),
+                item: "Y".to_string(),
+                value: "abc".to_string(),
+            })
+        );
+    }
+
+    fn text_item(item: &str) -> Option<String> {
+        let value = match item {
+            "サイズ" => "34.00",
+            "字間" | "行間" => "0.00",
+            "フォント" => "MS Gothic",
+            "文字色" => "ffffff",
+            "影・縁色" => "000000",
+            "文字装飾" => "標準文字",
+            "B" | "I" => "0",
+            _ => return None,
+        };
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn test_read_text_params() {
+        let params = TextParams::read(TEXT_EFFECT, text_item).unwrap();
+        assert_eq!(params.size, 34.0);
+        assert_eq!(params.font, "MS Gothic");
+    }
+
+    #[test]
+    fn test_spacing_ratio_range() {
+        let params = TextParams {
+            size: 40.0,
+            kern: 0.25,
+            lnsp: 8.0,
+            ..TextParams::default()
+        };
+        let absolute = params.clone().spacing_to_pixels(None);
+        assert_eq!((absolute.kern, absolute.lnsp), (0.25, 8.0));
+        let ratio = params.spacing_to_pixels(Some((-1.0, 1.0)));
+        assert_eq!((ratio.kern, ratio.lnsp), (10.0, 8.0));
+    }
+
+    #[test]
+    fn test_read_text_params_missing_item() {
+        let result = TextParams::read(TEXT_EFFECT, |item| {
+            (item != "フォント").then(|| text_item(item))?
+        });
+        assert_eq!(
+            result,
+            Err(SplitError::MissingItem {
+                effect: TEXT_EFFECT.to_string(),
+                item: "フォント".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_text_params_invalid_number() {
+        let result = TextParams::read(TEXT_EFFECT, |item| match item {
+            "字間" => Some("wide".to_string()),
+            _ => text_item(item),
+        });
+        assert!(matches!(result, Err(SplitError::InvalidNumber { item, .. }) if item == "字間"));
+    }
+
+    #[test]
+    fn test_find_text_effect_tries_names_in_order() {
+        let effects = vec!["カスタムテキスト".to_string(), TEXT_EFFECT.to_string()];
+        let read = |effect: &str, index: usize, item: &str| {
+            (effect == "カスタムテキスト" && index == 0 && item == "テキスト")
+                .then(|| "abc".to_string())
+        };
+        assert_eq!(
+            find_text_effect(&effects, read),
+            Some(("カスタムテキスト", 0, "abc".to_string()))
+        );
+        assert_eq!(find_text_effect(&effects[1..], read), None);
+    }
+
+    #[test]
+    fn test_find_text_falls_back_to_name() {
+        let effects = vec![TEXT_EFFECT.to_string()];
+        let read = |_: &str, _: usize, _: &str| None;
+        let found = find_text(&effects, read, || Some("abc".to_string()));
+        assert_eq!(found, Some((TextSource::Name, "abc".to_string())));
+        assert_eq!(
+            TextParams::read_source(TextSource::Name, read),
+            Ok(TextParams::default())
+        );
+        assert_eq!(find_text(&effects, read, || Some(String::new())), None);
+    }
+
+    #[test]
+    fn test_find_text_prefers_effect() {
+        let effects = vec![TEXT_EFFECT.to_string()];
+        let read = |_: &str, index: usize, item: &str| {
+            (index == 1 && item == "テキスト").then(|| "abc".to_string())
+        };
+        let found = find_text(&effects, read, || Some("name".to_string()));
+        assert_eq!(
+            found,
+            Some((TextSource::Effect(TEXT_EFFECT, 1), "abc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_find_text_effect_locates_index() {
+        let effects = vec![TEXT_EFFECT.to_string()];
+        let read = |effect: &str, index: usize, item: &str| {
+            (effect == TEXT_EFFECT && index == 2 && item == "テキスト").then(|| "abc".to_string())
+        };
+        assert_eq!(
+            find_text_effect(&effects, read),
+            Some((TEXT_EFFECT, 2, "abc".to_string()))
+        );
+    }
+}
@@ -1,24 +1,172 @@
 #![allow(dead_code)]
 
-#[derive(Debug)]
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HDir {
     Left,
     Mid,
     Right,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VDir {
     Top,
     Center,
     Bottom,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TextAlignment {
     pub hdir: HDir,
     pub vdir: VDir,
     pub is_vert: bool,
+    /// Alignment of the last line when it differs from `hdir`, e.g. a
+    /// centered last line under left-aligned ones.
+    pub last_line: Option<HDir>,
+}
+
+impl Default for TextAlignment {
+    /// 左寄せ[上], as on a newly created text object.
+    fn default() -> Self {
+        TextAlignment {
+            hdir: HDir::Left,
+            vdir: VDir::Top,
+            is_vert: false,
+            last_line: None,
+        }
+    }
+}
+
+fn canonical_hdir(h: HDir) -> &'static str {
+    match h {
+        HDir::Left => "left",
+        HDir::Mid => "center",
+        HDir::Right => "right",
+    }
+}
+
+fn parse_hdir(h: &str) -> Option<HDir> {
+    match h.trim() {
+        "left" => Some(HDir::Left),
+        "center" => Some(HDir::Mid),
+        "right" => Some(HDir::Right),
+        _ => None,
+    }
+}
+
+/// Splits a leading `<align:h,v>` directive, e.g. `<align:center,top>`, off
+/// the text. `h` is `left`, `center` or `right` and `v` is `top`, `center`
+/// or `bottom`; anything else leaves the text untouched. A third value like
+/// `h` aligns the last line differently, e.g. `<align:left,top,center>`.
+pub fn take_alignment_directive(input: &str) -> (Option<TextAlignment>, &str) {
+    let parsed = input.strip_prefix("<align:").and_then(|rest| {
+        let (content, rest) = rest.split_once('>')?;
+        Some((parse_canonical(content)?, rest))
+    });
+    match parsed {
+        Some((alignment, rest)) => (Some(alignment), rest),
+        None => (None, input),
+    }
+}
+
+/// Reads the `h,v` or `h,v,last` form used by the directive.
+fn parse_canonical(content: &str) -> Option<TextAlignment> {
+    let (h, v) = content.split_once(',')?;
+    let (v, last_line) = match v.split_once(',') {
+        Some((v, last)) => (v, Some(parse_hdir(last)?)),
+        None => (v, None),
+    };
+    let hdir = parse_hdir(h)?;
+    let vdir = match v.trim() {
+        "top" => VDir::Top,
+        "center" => VDir::Center,
+        "bottom" => VDir::Bottom,
+        _ => return None,
+    };
+    Some(TextAlignment {
+        hdir,
+        vdir,
+        is_vert: false,
+        last_line,
+    })
+}
+
+/// Reads either a 文字揃え label such as `中央揃え[上]`, as
+/// `parse_alignment` does, or the directive's form such as `center,top`.
+impl FromStr for TextAlignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(',') {
+            return parse_canonical(s).ok_or_else(|| format!("文字揃え {} を解釈できません", s));
+        }
+        if ["左", "中", "右", "上", "下", "縦書"]
+            .iter()
+            .any(|label| s.contains(label))
+        {
+            return Ok(parse_alignment(s));
+        }
+        Err(format!("文字揃え {} を解釈できません", s))
+    }
+}
+
+impl fmt::Display for HDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HDir::Left => "左寄せ",
+            HDir::Mid => "中央揃え",
+            HDir::Right => "右寄せ",
+        })
+    }
+}
+
+impl fmt::Display for VDir {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VDir::Top => "上",
+            VDir::Center => "中",
+            VDir::Bottom => "下",
+        })
+    }
+}
+
+/// Writes the 文字揃え label, e.g. `中央揃え[下]` or `縦書 上寄せ[右]`, which
+/// `parse_alignment` reads back to the same value. A different last line has
+/// no label, so it is written in the directive's form, e.g.
+/// `left,top,center`, which `FromStr` reads back.
+impl fmt::Display for TextAlignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(last_line) = self.last_line {
+            let vdir = match self.vdir {
+                VDir::Top => "top",
+                VDir::Center => "center",
+                VDir::Bottom => "bottom",
+            };
+            return write!(
+                f,
+                "{},{},{}",
+                canonical_hdir(self.hdir),
+                vdir,
+                canonical_hdir(last_line)
+            );
+        }
+        if !self.is_vert {
+            return write!(f, "{}[{}]", self.hdir, self.vdir);
+        }
+        let vdir = match self.vdir {
+            VDir::Top => "上寄せ",
+            VDir::Center => "中央揃え",
+            VDir::Bottom => "下寄せ",
+        };
+        let hdir = match self.hdir {
+            HDir::Left => "左",
+            HDir::Mid => "中",
+            HDir::Right => "右",
+        };
+        write!(f, "縦書 {}[{}]", vdir, hdir)
+    }
 }
 
 pub fn parse_alignment(input: &str) -> TextAlignment {
@@ -44,5 +192,77 @@ pub fn parse_alignment(input: &str) -> TextAlignment {
         hdir: h,
         vdir: v,
         is_vert,
+        last_line: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_take_alignment_directive() {
+        let (alignment, rest) = take_alignment_directive("<align:center,top>ab");
+        assert_eq!(alignment, Some(parse_alignment("中央揃え[上]")));
+        assert_eq!(rest, "ab");
+
+        assert_eq!(
+            take_alignment_directive("a<align:left,top>b"),
+            (None, "a<align:left,top>b")
+        );
+        assert_eq!(
+            take_alignment_directive("<align:up,top>b"),
+            (None, "<align:up,top>b")
+        );
+
+        let (alignment, _) = take_alignment_directive("<align:left,bottom,center>ab");
+        let alignment = alignment.unwrap();
+        assert_eq!((alignment.hdir, alignment.vdir), (HDir::Left, VDir::Bottom));
+        assert_eq!(alignment.last_line, Some(HDir::Mid));
+        assert_eq!(
+            take_alignment_directive("<align:left,top,up>b"),
+            (None, "<align:left,top,up>b")
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for hdir in [HDir::Left, HDir::Mid, HDir::Right] {
+            for vdir in [VDir::Top, VDir::Center, VDir::Bottom] {
+                for is_vert in [false, true] {
+                    let alignment = TextAlignment {
+                        hdir,
+                        vdir,
+                        is_vert,
+                        last_line: None,
+                    };
+                    assert_eq!(parse_alignment(&alignment.to_string()), alignment);
+                }
+            }
+        }
+        assert_eq!(parse_alignment("中央揃え[下]").to_string(), "中央揃え[下]");
+        assert_eq!(
+            parse_alignment("縦書 上寄せ[右]").to_string(),
+            "縦書 上寄せ[右]"
+        );
+    }
+
+    #[test]
+    fn test_from_str_accepts_both_forms() {
+        let label: TextAlignment = "中央揃え[上]".parse().unwrap();
+        let canonical: TextAlignment = "center, top".parse().unwrap();
+        assert_eq!(label, canonical);
+        assert_eq!(canonical.to_string().parse(), Ok(canonical));
+
+        let vertical: TextAlignment = "縦書 下寄せ[左]".parse().unwrap();
+        assert_eq!(vertical.to_string().parse(), Ok(vertical));
+
+        let last: TextAlignment = "left,bottom,right".parse().unwrap();
+        assert_eq!(last.last_line, Some(HDir::Right));
+        assert_eq!(last.to_string(), "left,bottom,right");
+        assert_eq!(last.to_string().parse(), Ok(last));
+
+        assert!("center,up".parse::<TextAlignment>().is_err());
+        assert!("".parse::<TextAlignment>().is_err());
     }
 }
@@ -1,47 +1,437 @@
+use crate::color::{mix, rotate_hue};
 use nom::{
     IResult, Parser,
     branch::alt,
-    bytes::complete::{tag, take_until, take_while1},
+    bytes::complete::{tag, take_while, take_while1},
     character::complete::char,
     combinator::map,
     multi::fold_many0,
     sequence::delimited,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json;
+use std::{borrow::Cow, collections::HashMap};
+use unicode_normalization::{UnicodeNormalization, is_nfc};
 
+#[derive(Serialize, Debug, PartialEq, Clone, Copy)]
+pub enum SizeSpec {
+    Absolute(f32),
+    /// Relative to the object's base size; resolved at layout time.
+    Multiplier(f32),
+    /// Explicitly the object's base size, from `<s=>`.
+    Base,
+    /// Whatever size fits the line to the configured width, from `<sauto>`;
+    /// resolved by the layout, the base size until then.
+    Auto,
+}
+
+impl SizeSpec {
+    pub fn resolve(self, base: f32) -> f32 {
+        match self {
+            SizeSpec::Absolute(size) => size,
+            SizeSpec::Multiplier(factor) => base * factor,
+            SizeSpec::Base | SizeSpec::Auto => base,
+        }
+    }
+
+    fn scaled(self, factor: f32) -> SizeSpec {
+        match self {
+            SizeSpec::Absolute(size) => SizeSpec::Absolute(size * factor),
+            SizeSpec::Multiplier(m) => SizeSpec::Multiplier(m * factor),
+            SizeSpec::Base => SizeSpec::Multiplier(factor),
+            SizeSpec::Auto => SizeSpec::Auto,
+        }
+    }
+}
+
+/// Per-glyph colors swinging between two colors along a sine wave, from
+/// `<colorfn:sine,from,to,period=N>`.
 #[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct ColorWave {
+    pub from: String,
+    pub to: String,
+    /// Glyphs from one `from` to the next.
+    pub period: f32,
+}
+
+impl ColorWave {
+    /// The color of the glyph at `index`, counted from the first glyph the
+    /// wave applies to: `from` at 0, `to` half a period later.
+    pub fn color_at(&self, index: usize) -> Option<String> {
+        let phase = std::f32::consts::TAU * index as f32 / self.period;
+        mix(&self.from, &self.to, (1.0 - phase.cos()) / 2.0)
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone, Default)]
 pub struct TextElement {
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub size: Option<f32>,
+    pub size: Option<SizeSpec>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub font: Option<String>,
+    /// Fonts listed after `font` in `<s,"A"|"B",>`, in fallback order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_fallbacks: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_bold: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_italic: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub color: Option<String>,
+    /// Colors cycled across the glyphs of this run, overriding `color`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Vec<String>>,
+    /// Wave computing each glyph's color, overriding `palette` and `color`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color_wave: Option<ColorWave>,
+    /// Degrees the color's hue is rotated by. Already applied to `color`;
+    /// kept for the object's own color when the markup sets none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hue_rotation: Option<f32>,
+    /// Start offsets in frames for the glyphs of this run, in order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timings: Option<Vec<usize>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deco: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_shift: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outline_width: Option<f32>,
+    /// Layer above the source object the first of the glyphs goes on, from
+    /// `<layer:N>`, instead of the next free one; the rest follow it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<usize>,
+    /// `Some(true)` inside a `<split>` region, `Some(false)` inside a
+    /// `<nosplit>` one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub line_index: Option<usize>,
+    /// Number of `<page>` breaks before this run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_index: Option<usize>,
+    /// Lists whose tag comes right before this run, so they start over here
+    /// even when the tag repeats the list already in effect.
+    #[serde(skip)]
+    pub restarts: ListRestarts,
     pub text: String,
 }
 
+/// Which of an element's cycled lists were opened by a tag of their own.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ListRestarts {
+    pub palette: bool,
+    pub color_wave: bool,
+    pub timings: bool,
+}
+
 #[allow(dead_code)]
 impl TextElement {
+    pub fn effective_size(&self, base: f32) -> f32 {
+        self.size.map_or(base, |size| size.resolve(base))
+    }
+
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string(&self)
     }
+
+    /// True when the element carries no style overrides; line and page
+    /// indices are structure and don't count.
+    pub fn is_default_style(&self) -> bool {
+        self.size.is_none()
+            && self.font.is_none()
+            && self.font_fallbacks.is_none()
+            && self.is_bold.is_none()
+            && self.is_italic.is_none()
+            && self.color.is_none()
+            && self.palette.is_none()
+            && self.color_wave.is_none()
+            && self.hue_rotation.is_none()
+            && self.timings.is_none()
+            && self.deco.is_none()
+            && self.baseline_shift.is_none()
+            && self.scale.is_none()
+            && self.outline_width.is_none()
+            && self.layer.is_none()
+            && self.split.is_none()
+    }
+}
+
+/// Pretty-prints parsed elements for inspecting how markup was understood.
+pub fn elements_to_pretty_json(elements: &[TextElement]) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(elements)
+}
+
+/// How far apart two numbers in consecutive elements may be for
+/// `elements_to_markup` to treat them as the same value.
+pub const MARKUP_EPSILON: f32 = 1e-4;
+
+/// Writes elements back as markup, emitting a tag only where a value
+/// differs from the previous element.
+pub fn elements_to_markup(elements: &[TextElement]) -> String {
+    elements_to_markup_with(elements, MARKUP_EPSILON)
+}
+
+/// Like `elements_to_markup`, treating numbers within `epsilon` of the
+/// previous element's as unchanged, so rounding noise doesn't restate tags.
+pub fn elements_to_markup_with(elements: &[TextElement], epsilon: f32) -> String {
+    let mut out = String::new();
+    let mut prev = Style::default().to_element("");
+    for el in elements {
+        let el = &snap_numbers(el, &prev, epsilon);
+        let pages = el
+            .page_index
+            .unwrap_or(0)
+            .saturating_sub(prev.page_index.unwrap_or(0));
+        out.push_str(&"<page>".repeat(pages));
+        if el.is_default_style() {
+            if prev.split.is_some() {
+                out.push_str(region_tag(None, prev.split));
+            }
+            let styled = TextElement {
+                split: None,
+                ..prev.clone()
+            };
+            if !styled.is_default_style() {
+                out.push_str("<reset>");
+            }
+        } else {
+            push_style_changes(&mut out, &prev, el);
+        }
+        out.push_str(&el.text);
+        prev = el.clone();
+    }
+    out
+}
+
+/// `el` with every number within `epsilon` of `prev`'s replaced by
+/// `prev`'s. The replaced values carry over, so slow drift still shows up
+/// once it adds up to more than `epsilon`.
+fn snap_numbers(el: &TextElement, prev: &TextElement, epsilon: f32) -> TextElement {
+    let near = |a: f32, b: f32| (a - b).abs() <= epsilon;
+    let snap = |value: Option<f32>, prev: Option<f32>| match (value, prev) {
+        (Some(a), Some(b)) if near(a, b) => prev,
+        _ => value,
+    };
+    let size = match (el.size, prev.size) {
+        (Some(SizeSpec::Absolute(a)), Some(SizeSpec::Absolute(b)))
+        | (Some(SizeSpec::Multiplier(a)), Some(SizeSpec::Multiplier(b)))
+            if near(a, b) =>
+        {
+            prev.size
+        }
+        _ => el.size,
+    };
+    let color_wave = match (&el.color_wave, &prev.color_wave) {
+        (Some(a), Some(b)) if a.from == b.from && a.to == b.to && near(a.period, b.period) => {
+            prev.color_wave.clone()
+        }
+        _ => el.color_wave.clone(),
+    };
+    TextElement {
+        size,
+        color_wave,
+        hue_rotation: snap(el.hue_rotation, prev.hue_rotation),
+        baseline_shift: snap(el.baseline_shift, prev.baseline_shift),
+        scale: snap(el.scale, prev.scale),
+        outline_width: snap(el.outline_width, prev.outline_width),
+        ..el.clone()
+    }
+}
+
+fn push_style_changes(out: &mut String, prev: &TextElement, el: &TextElement) {
+    let number = |tag: &str, value: Option<f32>| {
+        format!(
+            "<{}{}>",
+            tag,
+            value.map(|v| v.to_string()).unwrap_or_default()
+        )
+    };
+    let list = |tag: &str, values: Option<Vec<String>>| match values {
+        Some(values) => format!("<{}:{}>", tag, values.join(",")),
+        None => format!("<{}>", tag),
+    };
+
+    if (
+        &el.size,
+        &el.font,
+        &el.font_fallbacks,
+        el.is_bold,
+        el.is_italic,
+    ) != (
+        &prev.size,
+        &prev.font,
+        &prev.font_fallbacks,
+        prev.is_bold,
+        prev.is_italic,
+    ) {
+        // A multiplier scales whatever size is current, so clear it first.
+        if matches!(el.size, Some(SizeSpec::Multiplier(_))) && prev.size.is_some() {
+            out.push_str("<s>");
+        }
+        out.push_str(&style_tag(el));
+    }
+    if el.hue_rotation != prev.hue_rotation {
+        out.push_str(&number("hue", el.hue_rotation));
+    }
+    if el.color != prev.color || (el.color.is_some() && el.hue_rotation != prev.hue_rotation) {
+        match (&el.color, el.hue_rotation) {
+            // The element's color already has the rotation applied.
+            (Some(color), Some(degrees)) => {
+                let source = rotate_hue(color, -degrees).unwrap_or_else(|| color.clone());
+                out.push_str(&format!("<#{}>", source));
+            }
+            (Some(color), None) => out.push_str(&format!("<#{}>", color)),
+            (None, _) => out.push_str("<#>"),
+        }
+    }
+    if el.palette != prev.palette || el.restarts.palette {
+        out.push_str(&list("palette", el.palette.clone()));
+    }
+    if el.color_wave != prev.color_wave || el.restarts.color_wave {
+        let wave = el.color_wave.as_ref().map(|wave| {
+            vec![
+                "sine".to_string(),
+                wave.from.clone(),
+                wave.to.clone(),
+                format!("period={}", wave.period),
+            ]
+        });
+        out.push_str(&list("colorfn", wave));
+    }
+    if el.timings != prev.timings || el.restarts.timings {
+        let timings = el
+            .timings
+            .as_ref()
+            .map(|t| t.iter().map(usize::to_string).collect());
+        out.push_str(&list("timing", timings));
+    }
+    if el.deco != prev.deco {
+        out.push_str(&list("deco", el.deco.clone().map(|deco| vec![deco])));
+    }
+    if el.baseline_shift != prev.baseline_shift {
+        out.push_str(&number("y", el.baseline_shift));
+    }
+    if el.scale != prev.scale {
+        out.push_str(&number("scale", el.scale));
+    }
+    if el.outline_width != prev.outline_width {
+        out.push_str(&number("ow", el.outline_width));
+    }
+    if el.layer != prev.layer {
+        out.push_str(&list(
+            "layer",
+            el.layer.map(|layer| vec![layer.to_string()]),
+        ));
+    }
+    if el.split != prev.split {
+        out.push_str(region_tag(el.split, prev.split));
+    }
+}
+
+/// The tag entering the region `split`, or leaving `prev` when `split` is
+/// `None`.
+fn region_tag(split: Option<bool>, prev: Option<bool>) -> &'static str {
+    match (split, prev) {
+        (Some(true), _) => "<split>",
+        (Some(false), _) => "<nosplit>",
+        (None, Some(false)) => "</nosplit>",
+        (None, _) => "</split>",
+    }
+}
+
+/// The `<s…>` tag restating an element's size, fonts and flags.
+fn style_tag(el: &TextElement) -> String {
+    if el.size.is_none() && el.font.is_none() && el.is_bold.is_none() && el.is_italic.is_none() {
+        return "<s>".to_string();
+    }
+    let size = match el.size {
+        Some(SizeSpec::Absolute(size)) => size.to_string(),
+        Some(SizeSpec::Multiplier(factor)) => format!("*{}", factor),
+        Some(SizeSpec::Base) => "=".to_string(),
+        Some(SizeSpec::Auto) => "auto".to_string(),
+        None => String::new(),
+    };
+    let fonts: Vec<&str> = el
+        .font
+        .iter()
+        .chain(el.font_fallbacks.iter().flatten())
+        .map(String::as_str)
+        .collect();
+    let flags = match (el.is_bold, el.is_italic) {
+        (None, None) => String::new(),
+        (bold, italic) => {
+            let flags = format!(
+                "{}{}",
+                if bold == Some(true) { "B" } else { "" },
+                if italic == Some(true) { "I" } else { "" }
+            );
+            // An empty segment would clear the flags rather than unset them.
+            if flags.is_empty() {
+                " ".to_string()
+            } else {
+                flags
+            }
+        }
+    };
+    format!("<s{},{},{}>", size, fonts.join("|"), flags)
+}
+
+/// What happens to a `<...>` fragment that no tag parser accepts.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnknownTagPolicy {
+    /// Keep it as literal text.
+    #[default]
+    Keep,
+    /// Remove it from the text.
+    Drop,
+    /// Fail the parse, naming the tag.
+    Error,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Extra string treated as a line break, e.g. `{{br}}`.
+    pub newline_marker: Option<String>,
+    /// Compose the input to NFC so decomposed accents stay on their glyph.
+    pub normalize_nfc: bool,
+    /// Log warnings for suspicious markup instead of silently ignoring it.
+    pub strict: bool,
+    pub unknown_tags: UnknownTagPolicy,
+    /// Make `<#>` return to the color before the last `<#…>` instead of
+    /// clearing the color.
+    pub color_stack: bool,
+    /// Letters accepted in a style tag's flags in place of `B` and `I`,
+    /// e.g. `太` for `B`.
+    pub flag_aliases: HashMap<char, char>,
 }
 
 #[derive(Clone, Debug)]
 struct Style {
-    size: Option<f32>,
+    size: Option<SizeSpec>,
     font: Option<String>,
+    font_fallbacks: Option<Vec<String>>,
     is_bold: Option<bool>,
     is_italic: Option<bool>,
     color: Option<String>,
+    palette: Option<Vec<String>>,
+    color_wave: Option<ColorWave>,
+    hue_rotation: Option<f32>,
+    timings: Option<Vec<usize>>,
+    deco: Option<String>,
+    baseline_shift: Option<f32>,
+    scale: Option<f32>,
+    outline_width: Option<f32>,
+    layer: Option<usize>,
+    split: Option<bool>,
     line_index: usize,
+    page_index: usize,
+    restarts: ListRestarts,
+    /// Colors replaced by `<#…>`, restored by `<#>` in stack mode.
+    previous_colors: Vec<Option<String>>,
 }
 
 impl Default for Style {
@@ -49,39 +439,143 @@ impl Default for Style {
         Style {
             size: None,
             font: None,
+            font_fallbacks: None,
             is_bold: None,
             is_italic: None,
             color: None,
+            palette: None,
+            color_wave: None,
+            hue_rotation: None,
+            timings: None,
+            deco: None,
+            baseline_shift: None,
+            scale: None,
+            outline_width: None,
+            layer: None,
+            split: None,
             line_index: 0,
+            page_index: 0,
+            restarts: ListRestarts::default(),
+            previous_colors: Vec::new(),
+        }
+    }
+}
+
+impl Style {
+    /// Sets the color, remembering the replaced one when `stack` is on.
+    fn set_color(&mut self, color: String, stack: bool) {
+        if stack {
+            self.previous_colors.push(self.color.take());
+        }
+        self.color = Some(color);
+    }
+
+    fn to_element(&self, text: &str) -> TextElement {
+        TextElement {
+            size: self.size,
+            font: self.font.clone(),
+            font_fallbacks: self.font_fallbacks.clone(),
+            is_bold: self.is_bold,
+            is_italic: self.is_italic,
+            color: match (&self.color, self.hue_rotation) {
+                (Some(color), Some(degrees)) => {
+                    Some(rotate_hue(color, degrees).unwrap_or_else(|| color.clone()))
+                }
+                (color, _) => color.clone(),
+            },
+            palette: self.palette.clone(),
+            color_wave: self.color_wave.clone(),
+            hue_rotation: self.hue_rotation,
+            timings: self.timings.clone(),
+            deco: self.deco.clone(),
+            baseline_shift: self.baseline_shift,
+            scale: self.scale,
+            outline_width: self.outline_width,
+            layer: self.layer,
+            split: self.split,
+            line_index: Some(self.line_index),
+            page_index: Some(self.page_index),
+            restarts: self.restarts,
+            text: text.to_string(),
         }
     }
 }
 
+/// Markup names for the 文字装飾 values, alongside the labels themselves.
+const DECORATIONS: &[(&str, &str)] = &[
+    ("normal", "標準文字"),
+    ("shadow", "影付き文字"),
+    ("shadow-light", "影付き文字(薄)"),
+    ("outline", "縁取り文字"),
+    ("outline-thin", "縁取り文字(細)"),
+];
+
+/// Text of the element emitted for a line break, whether the source used a
+/// real newline or the literal `\n` escape.
+pub const NEWLINE: &str = "\n";
+
+/// Substrings at which a text run stops so the tag parsers get a chance.
+const TAG_OPENERS: &[&str] = &[
+    "<s", "<#", "<#>", "<reset>", "</", "<deco", "<palette", "<colorfn", "<hue", "<timing", "<y",
+    "<ow", "<layer", "<split>", "<nosplit", "<page>", "\\n", "\\t", "\\<", "\n", "\r",
+];
+
 enum Action<'a> {
     UpdateStyle(
         (
-            Option<Option<f32>>,
-            Option<Option<String>>,
-            Option<Option<(bool, bool)>>,
+            Option<Option<SizeSpec>>,
+            Option<Option<Vec<String>>>,
+            Option<Option<&'a str>>,
+            Option<String>,
         ),
     ),
     ResetStyle,
     UpdateColor(String),
     ResetColor,
+    UpdatePalette(Option<Vec<String>>),
+    UpdateColorWave(Option<ColorWave>),
+    UpdateHue(Option<f32>),
+    UpdateTimings(Option<Vec<usize>>),
+    UpdateDeco(Option<String>),
+    UpdateBaselineShift(Option<f32>),
+    UpdateScale(Option<f32>),
+    UpdateOutlineWidth(Option<f32>),
+    UpdateLayer(Option<usize>),
+    SplitRegion(Option<bool>),
+    PageBreak,
+    ResetAll,
     AppendText(&'a str, usize),
 }
 
+/// The inside of a tag, up to its `>`. An unclosed tag fails at the next
+/// `<` or line break rather than reaching into the tags after it, so only
+/// the malformed fragment stays text.
+fn tag_content(input: &str) -> IResult<&str, &str> {
+    take_while(|c| !matches!(c, '>' | '<' | '\n' | '\r')).parse(input)
+}
+
+/// Parses `<s size,font,flags>`. Every segment that is present sets its
+/// field and an empty one clears it, while missing segments leave theirs
+/// alone:
+///
+/// - `<s,>` clears the size and the font, keeping the flags.
+/// - `<s,,>` clears the size, the font and the flags, like `<s>`.
+/// - `<s,,,>` is `<s,,>`; segments past the third are ignored.
+///
+/// The flag segment is returned as written, for `read_flags` to resolve
+/// with the configured aliases.
 fn parse_optional_param(
     input: &str,
 ) -> IResult<
     &str,
     (
-        Option<Option<f32>>,
-        Option<Option<String>>,
-        Option<Option<(bool, bool)>>,
+        Option<Option<SizeSpec>>,
+        Option<Option<Vec<String>>>,
+        Option<Option<&str>>,
+        Option<String>,
     ),
 > {
-    let (input, content) = delimited(char('<'), take_until(">"), char('>')).parse(input)?;
+    let (input, content) = delimited(char('<'), tag_content, char('>')).parse(input)?;
     if !content.starts_with('s') || content == "s" {
         return Err(nom::Err::Error(nom::error::Error::new(
             input,
@@ -89,30 +583,155 @@ fn parse_optional_param(
         )));
     }
 
+    // `<s=>` re-asserts every object value, unlike `<s>` which only clears
+    // the overrides.
+    if content == "s=" {
+        return Ok((
+            input,
+            (Some(Some(SizeSpec::Base)), Some(None), Some(None), None),
+        ));
+    }
+
     let parts: Vec<&str> = content.split(',').collect();
 
+    // Sizes are ASCII only, so full-width digits like `<s２０>` stay text.
+    if !parts[0].is_ascii() {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+
+    // `<s#ff0000,…>` sets the color in place of the size.
+    let color = match parts[0].strip_prefix("s#") {
+        Some(hex) if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Some(hex.to_string())
+        }
+        Some(_) => {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                input,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        None => None,
+    };
+
     let size = parts
         .get(0)
         .and_then(|s| s.get(1..))
-        .map(|s| s.parse::<f32>().ok());
+        .filter(|_| color.is_none())
+        .map(|s| {
+            if s == "=" {
+                Some(SizeSpec::Base)
+            } else if s == "auto" {
+                Some(SizeSpec::Auto)
+            } else if let Some(factor) = s.strip_prefix('*') {
+                factor.parse::<f32>().ok().map(SizeSpec::Multiplier)
+            } else {
+                s.parse::<f32>().ok().map(SizeSpec::Absolute)
+            }
+        });
 
     let font = parts.get(1).map(|s| {
-        if s.is_empty() {
-            None
-        } else {
-            Some(s.to_string())
-        }
+        let fonts = parse_font_list(s);
+        if fonts.is_empty() { None } else { Some(fonts) }
     });
 
-    let flags = parts.get(2).map(|s| {
-        if s.is_empty() {
-            None
-        } else {
-            Some((s.contains('B'), s.contains('I')))
-        }
-    });
+    let flags = parts
+        .get(2)
+        .map(|s| if s.is_empty() { None } else { Some(*s) });
+
+    Ok((input, (size, font, flags, color)))
+}
+
+/// Whether a style tag's flag segment sets bold and italic, reading the
+/// letters in `aliases` as the flags they stand for.
+fn read_flags(segment: &str, aliases: &HashMap<char, char>) -> (bool, bool) {
+    let flags = resolve_flag_aliases(segment, aliases);
+    (flags.contains('B'), flags.contains('I'))
+}
+
+fn resolve_flag_aliases<'a>(segment: &'a str, aliases: &HashMap<char, char>) -> Cow<'a, str> {
+    if !segment.chars().any(|c| aliases.contains_key(&c)) {
+        return Cow::Borrowed(segment);
+    }
+    Cow::Owned(
+        segment
+            .chars()
+            .map(|c| aliases.get(&c).copied().unwrap_or(c))
+            .collect(),
+    )
+}
+
+/// Splits a font segment such as `"Noto Sans JP"|"Arial"` into its names,
+/// dropping surrounding quotes.
+fn parse_font_list(segment: &str) -> Vec<String> {
+    segment
+        .split('|')
+        .map(|name| {
+            let name = name.trim();
+            name.strip_prefix('"')
+                .and_then(|n| n.strip_suffix('"'))
+                .unwrap_or(name)
+        })
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Describes anything in a style tag's flag segment other than `B`, `I` and
+/// whitespace, plus repeated flags; `None` when the segment is clean.
+fn check_flags(segment: &str) -> Option<String> {
+    let stray: String = segment
+        .chars()
+        .filter(|c| !matches!(c, 'B' | 'I') && !c.is_whitespace())
+        .collect();
+    let repeated: String = ['B', 'I']
+        .into_iter()
+        .filter(|flag| segment.matches(*flag).count() > 1)
+        .collect();
+    match (stray.is_empty(), repeated.is_empty()) {
+        (true, true) => None,
+        (false, _) => Some(format!(
+            "フラグ \"{}\" に不明な文字 \"{}\" があります",
+            segment, stray
+        )),
+        (true, false) => Some(format!(
+            "フラグ \"{}\" で {} が重複しています",
+            segment, repeated
+        )),
+    }
+}
 
-    Ok((input, (size, font, flags)))
+/// Collects warnings for the flag segments of every style tag in `input`.
+pub fn flag_warnings(input: &str) -> Vec<String> {
+    flag_diagnostics(input, &HashMap::new())
+        .into_iter()
+        .map(|diagnostic| diagnostic.message)
+        .collect()
+}
+
+/// Checks the flag segment of every style tag in `input`, with the letters
+/// in `aliases` read as their flags. Escaped `\<s…>` is text, not a tag.
+fn flag_diagnostics(input: &str, aliases: &HashMap<char, char>) -> Vec<Diagnostic> {
+    input
+        .match_indices("<s")
+        .filter(|&(pos, _)| !input[..pos].ends_with('\\'))
+        .filter_map(|(pos, _)| {
+            let rest = &input[pos..];
+            match parse_tag(rest) {
+                Ok((_, Action::UpdateStyle(_))) => {}
+                _ => return None,
+            }
+            let content = &rest[2..rest.find('>')?];
+            let segment = content.split(',').nth(2)?;
+            let message = check_flags(&resolve_flag_aliases(segment, aliases))?;
+            Some(Diagnostic {
+                position: pos,
+                message,
+            })
+        })
+        .collect()
 }
 
 fn parse_color(input: &str) -> IResult<&str, String> {
@@ -127,21 +746,154 @@ fn parse_color(input: &str) -> IResult<&str, String> {
     .parse(input)
 }
 
-fn take_until_any<'a>(patterns: &[&'static str]) -> impl Fn(&'a str) -> IResult<&'a str, &'a str> {
-    move |input: &'a str| {
-        let mut min_pos = input.len();
-        for pattern in patterns {
-            if let Some(pos) = input.find(pattern) {
-                min_pos = min_pos.min(pos);
-            }
-        }
-        let (text, rest) = input.split_at(min_pos);
-        Ok((rest, text))
+fn parse_palette(input: &str) -> IResult<&str, Option<Vec<String>>> {
+    let (rest, content) = delimited(tag("<palette"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    let colors: Option<Vec<String>> = content.strip_prefix(':').and_then(|list| {
+        list.split(',')
+            .map(str::trim)
+            .map(|c| {
+                (!c.is_empty() && c.chars().all(|ch| ch.is_ascii_hexdigit())).then(|| c.to_string())
+            })
+            .collect()
+    });
+    colors.map(|colors| (rest, Some(colors))).ok_or_else(|| {
+        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))
+    })
+}
+
+/// `<colorfn:sine,from,to,period=N>` with a positive period; `sine` is the
+/// only function so far.
+fn parse_color_wave(input: &str) -> IResult<&str, Option<ColorWave>> {
+    let (rest, content) = delimited(tag("<colorfn"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    let is_color = |c: &str| c.len() == 6 && c.chars().all(|ch| ch.is_ascii_hexdigit());
+    let wave = content.strip_prefix(':').and_then(|spec| {
+        let parts: Vec<&str> = spec.split(',').map(str::trim).collect();
+        let [kind, from, to, period] = parts[..] else {
+            return None;
+        };
+        let period = period.strip_prefix("period=")?.trim().parse::<f32>().ok()?;
+        (kind == "sine" && is_color(from) && is_color(to) && period > 0.0).then(|| ColorWave {
+            from: from.to_string(),
+            to: to.to_string(),
+            period,
+        })
+    });
+    wave.map(|wave| (rest, Some(wave))).ok_or_else(|| {
+        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))
+    })
+}
+
+fn parse_hue(input: &str) -> IResult<&str, Option<f32>> {
+    let (rest, content) = delimited(tag("<hue"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    content
+        .parse::<f32>()
+        .map(|degrees| (rest, Some(degrees)))
+        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))
+}
+
+fn parse_timings(input: &str) -> IResult<&str, Option<Vec<usize>>> {
+    let (rest, content) = delimited(tag("<timing"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    let timings: Option<Vec<usize>> = content
+        .strip_prefix(':')
+        .and_then(|list| list.split(',').map(|t| t.trim().parse().ok()).collect());
+    timings.map(|timings| (rest, Some(timings))).ok_or_else(|| {
+        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))
+    })
+}
+
+fn parse_decoration(input: &str) -> IResult<&str, Option<String>> {
+    let (rest, content) = delimited(tag("<deco"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    let name = content.strip_prefix(':').unwrap_or("");
+    DECORATIONS
+        .iter()
+        .find(|(alias, label)| *alias == name || *label == name)
+        .map(|(_, label)| (rest, Some(label.to_string())))
+        .ok_or_else(|| {
+            nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Verify))
+        })
+}
+
+fn parse_baseline_shift(input: &str) -> IResult<&str, Option<f32>> {
+    let (rest, content) = delimited(tag("<y"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    content
+        .parse::<f32>()
+        .map(|shift| (rest, Some(shift)))
+        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))
+}
+
+fn parse_scale(input: &str) -> IResult<&str, Option<f32>> {
+    let (rest, content) = delimited(tag("<scale"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    content
+        .parse::<f32>()
+        .map(|scale| (rest, Some(scale)))
+        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))
+}
+
+fn parse_outline_width(input: &str) -> IResult<&str, Option<f32>> {
+    let (rest, content) = delimited(tag("<ow"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    match content.parse::<f32>() {
+        Ok(width) if width >= 0.0 => Ok((rest, Some(width))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        ))),
+    }
+}
+
+/// `<layer:N>` with `N` at least 1, as layer 0 is the source itself.
+fn parse_layer(input: &str) -> IResult<&str, Option<usize>> {
+    let (rest, content) = delimited(tag("<layer"), tag_content, char('>')).parse(input)?;
+    if content.is_empty() {
+        return Ok((rest, None));
+    }
+    match content.strip_prefix(':').map(|n| n.trim().parse::<usize>()) {
+        Some(Ok(layer)) if layer >= 1 => Ok((rest, Some(layer))),
+        _ => Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        ))),
     }
 }
 
 fn parse_newline(input: &str) -> IResult<&str, (&str, usize)> {
-    map(tag("\\n"), |s: &str| (s, 1)).parse(input)
+    // "\r\n" must come before the lone "\r" so it counts as a single break.
+    map(alt((tag("\\n"), tag("\r\n"), tag("\r"), tag("\n"))), |_| {
+        (NEWLINE, 1)
+    })
+    .parse(input)
+}
+
+fn parse_tab(input: &str) -> IResult<&str, (&str, usize)> {
+    map(tag("\\t"), |_| ("\t", 0)).parse(input)
+}
+
+/// `\<` for a literal `<` that never starts a tag.
+fn parse_escaped_opener(input: &str) -> IResult<&str, (&str, usize)> {
+    map(tag("\\<"), |_| ("<", 0)).parse(input)
 }
 
 fn parse_text_greedy(input: &str) -> IResult<&str, &str> {
@@ -152,38 +904,275 @@ fn parse_text_greedy(input: &str) -> IResult<&str, &str> {
         )));
     }
 
-    // This parser takes all characters until it finds "<s", "<#", "<#>" or "\\n"
-    let (rest, text) = take_until_any(&["<s", "<#", "<#>", "\\n"])(input)?;
+    // This parser takes all characters until it finds one of `TAG_OPENERS`
+    // that actually starts a tag; openers no tag parser accepts stay literal.
+    // A single forward pass: an escaped `\<` is an opener itself, so the scan
+    // stops at its backslash before ever reaching the `<`.
+    let pos = input
+        .char_indices()
+        .map(|(pos, _)| pos)
+        .find(|&pos| {
+            let rest = &input[pos..];
+            TAG_OPENERS.iter().any(|opener| rest.starts_with(opener)) && parse_tag(rest).is_ok()
+        })
+        .unwrap_or(input.len());
+    let (text, rest) = input.split_at(pos);
     Ok((rest, text))
 }
 
-fn parse_action(input: &'_ str) -> IResult<&'_ str, Action<'_>> {
+/// `<split>` and `<nosplit>` regions and their closing tags.
+fn parse_region(input: &str) -> IResult<&str, Option<bool>> {
+    alt((
+        map(tag("<split>"), |_| Some(true)),
+        map(tag("<nosplit>"), |_| Some(false)),
+        map(alt((tag("</split>"), tag("</nosplit>"))), |_| None),
+    ))
+    .parse(input)
+}
+
+fn parse_tag(input: &'_ str) -> IResult<&'_ str, Action<'_>> {
     alt((
+        // Must precede the style tag, which would read "<scale..>" as a size.
+        map(parse_scale, Action::UpdateScale),
+        map(parse_outline_width, Action::UpdateOutlineWidth),
+        map(parse_layer, Action::UpdateLayer),
+        map(parse_region, Action::SplitRegion),
+        map(tag("<page>"), |_| Action::PageBreak),
         map(parse_optional_param, Action::UpdateStyle),
         map(tag("<s>"), |_| Action::ResetStyle),
         map(parse_color, Action::UpdateColor),
         map(tag("<#>"), |_| Action::ResetColor),
+        map(alt((tag("<reset>"), tag("</>"))), |_| Action::ResetAll),
+        map(parse_decoration, Action::UpdateDeco),
+        map(parse_palette, Action::UpdatePalette),
+        map(parse_color_wave, Action::UpdateColorWave),
+        map(parse_hue, Action::UpdateHue),
+        map(parse_timings, Action::UpdateTimings),
+        map(parse_baseline_shift, Action::UpdateBaselineShift),
         map(parse_newline, |(s, c)| Action::AppendText(s, c)),
+        map(parse_tab, |(s, c)| Action::AppendText(s, c)),
+        map(parse_escaped_opener, |(s, c)| Action::AppendText(s, c)),
+    ))
+    .parse(input)
+}
+
+fn parse_action(input: &'_ str) -> IResult<&'_ str, Action<'_>> {
+    alt((
+        parse_tag,
         map(parse_text_greedy, |s| Action::AppendText(s, 0)),
     ))
     .parse(input)
 }
 
+/// Finds every `<...>` fragment that isn't a known tag, with its byte
+/// position.
+fn unknown_tags(input: &str) -> Vec<(usize, &str)> {
+    let mut fragments = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = input[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &input[start..];
+        if input[..start].ends_with('\\') {
+            pos = start + 1;
+            continue;
+        }
+        if let Ok((after, _)) = parse_tag(rest) {
+            pos = input.len() - after.len();
+            continue;
+        }
+        // Only a `<` closed before the next `<` forms a tag.
+        let fragment = rest[1..]
+            .find(['<', '>'])
+            .filter(|&i| rest.as_bytes()[i + 1] == b'>')
+            .map(|i| &rest[..i + 2]);
+        match fragment {
+            Some(tag) => {
+                fragments.push((start, tag));
+                pos = start + tag.len();
+            }
+            None => pos = start + 1,
+        }
+    }
+    fragments
+}
+
+/// Applies `policy` to every `<...>` fragment that isn't a known tag.
+fn apply_unknown_tag_policy<'a>(
+    input: &'a str,
+    policy: UnknownTagPolicy,
+) -> Result<Cow<'a, str>, String> {
+    if policy == UnknownTagPolicy::Keep {
+        return Ok(Cow::Borrowed(input));
+    }
+    let fragments = unknown_tags(input);
+    if let (UnknownTagPolicy::Error, Some((start, tag))) = (policy, fragments.first()) {
+        return Err(format!("Unknown tag {} at byte {}", tag, start));
+    }
+    if fragments.is_empty() {
+        return Ok(Cow::Borrowed(input));
+    }
+    let mut output = String::with_capacity(input.len());
+    let mut pos = 0;
+    for (start, tag) in fragments {
+        output.push_str(&input[pos..start]);
+        pos = start + tag.len();
+    }
+    output.push_str(&input[pos..]);
+    Ok(Cow::Owned(output))
+}
+
 pub fn parse_markup(input: &str) -> Result<Vec<TextElement>, String> {
-    let (rem, (elements, _)) = fold_many0(
+    parse_markup_with(input, &ParseOptions::default())
+}
+
+pub fn parse_markup_with(input: &str, options: &ParseOptions) -> Result<Vec<TextElement>, String> {
+    parse_markup_with_end(input, options).map(|(elements, _)| elements)
+}
+
+/// Like `parse_markup_with`, also returning the style at the end of `input`
+/// as an element without text, the way `style_at` does; its line and page
+/// count any breaks after the last element.
+pub fn parse_markup_with_end(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(Vec<TextElement>, TextElement), String> {
+    let input = match options.newline_marker.as_deref() {
+        Some(marker) if !marker.is_empty() && input.contains(marker) => {
+            Cow::Owned(input.replace(marker, NEWLINE))
+        }
+        _ => Cow::Borrowed(input),
+    };
+    let input = if options.normalize_nfc && !is_nfc(&input) {
+        Cow::Owned(input.nfc().collect())
+    } else {
+        input
+    };
+    let input = match apply_unknown_tag_policy(&input, options.unknown_tags)? {
+        Cow::Owned(output) => Cow::Owned(output),
+        Cow::Borrowed(_) => input,
+    };
+    let input = input.as_ref();
+
+    if options.strict {
+        for diagnostic in flag_diagnostics(input, &options.flag_aliases) {
+            aviutl2::log::warn!("{}", diagnostic.message);
+        }
+    }
+
+    // Fast path: without any tag opener, escape or newline the whole input is
+    // a single unstyled run, so skip the combinator stack entirely.
+    if !input.contains(['<', '\\', '\n', '\r']) {
+        let end = Style::default().to_element("");
+        if input.is_empty() {
+            return Ok((Vec::new(), end));
+        }
+        return Ok((vec![Style::default().to_element(input)], end));
+    }
+
+    parse_markup_full(input, options)
+}
+
+/// The style in effect at the `char_index`th character of `input`, i.e. for
+/// text inserted there: every tag ending at or before it applies, while one
+/// the index falls inside doesn't yet. Returned as an element without text;
+/// past the end, it is the style at the end. Uses the default options.
+pub fn style_at(input: &str, char_index: usize) -> TextElement {
+    let end = input
+        .char_indices()
+        .nth(char_index)
+        .map_or(input.len(), |(pos, _)| pos);
+    // The prefix can't complete a tag the index is inside, as every tag
+    // ends with `>`.
+    let style = match fold_actions(&input[..end], &ParseOptions::default()) {
+        Ok((_, (_, style))) => style,
+        Err(_) => Style::default(),
+    };
+    style.to_element("")
+}
+
+/// A problem found while parsing leniently, at a byte position of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub position: usize,
+    pub message: String,
+}
+
+/// Parses `input` without failing: unknown tags and anything left unparsed
+/// stay literal text and are reported as diagnostics, along with suspicious
+/// style flags. Meant for editors that show problems while the user types.
+pub fn parse_markup_lenient(input: &str) -> (Vec<TextElement>, Vec<Diagnostic>) {
+    let mut diagnostics: Vec<Diagnostic> = unknown_tags(input)
+        .into_iter()
+        .map(|(position, tag)| Diagnostic {
+            position,
+            message: format!("Unknown tag {}", tag),
+        })
+        .collect();
+    diagnostics.extend(flag_diagnostics(input, &HashMap::new()));
+
+    let (elements, rem) = match fold_actions(input, &ParseOptions::default()) {
+        Ok((rem, (mut elements, style))) => {
+            if !rem.is_empty() {
+                elements.push(style.to_element(rem));
+            }
+            (elements, rem)
+        }
+        Err(_) => (vec![Style::default().to_element(input)], input),
+    };
+    if !rem.is_empty() {
+        diagnostics.push(Diagnostic {
+            position: input.len() - rem.len(),
+            message: format!("Unparsed input remaining: {}", rem),
+        });
+    }
+    diagnostics.sort_by_key(|diagnostic| diagnostic.position);
+    (elements, diagnostics)
+}
+
+fn parse_markup_full(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(Vec<TextElement>, TextElement), String> {
+    let (rem, (elements, style)) = fold_actions(input, options).map_err(|e| e.to_string())?;
+
+    if rem.is_empty() {
+        Ok((elements, style.to_element("")))
+    } else {
+        Err(format!("Unparsed input remaining: {}", rem))
+    }
+}
+
+/// Folds every action in `input` into elements, returning the final style
+/// along with them.
+fn fold_actions<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> IResult<&'a str, (Vec<TextElement>, Style)> {
+    fold_many0(
         parse_action,
         || (Vec::<TextElement>::new(), Style::default()),
         |(mut elements, mut style), action| {
             match action {
-                Action::UpdateStyle((size, font, flags)) => {
+                Action::UpdateStyle((size, font, flags, color)) => {
+                    if let Some(color) = color {
+                        style.set_color(color, options.color_stack);
+                    }
                     if let Some(s) = size {
-                        style.size = s;
+                        style.size = match (s, style.size) {
+                            (Some(SizeSpec::Multiplier(factor)), Some(current)) => {
+                                Some(current.scaled(factor))
+                            }
+                            _ => s,
+                        };
                     }
                     if let Some(f) = font {
-                        style.font = f;
+                        let mut fonts = f.unwrap_or_default().into_iter();
+                        style.font = fonts.next();
+                        let fallbacks: Vec<String> = fonts.collect();
+                        style.font_fallbacks = (!fallbacks.is_empty()).then_some(fallbacks);
                     }
                     if let Some(fl) = flags {
-                        if let Some((b, i)) = fl {
+                        if let Some((b, i)) = fl.map(|s| read_flags(s, &options.flag_aliases)) {
                             style.is_bold = Some(b);
                             style.is_italic = Some(i);
                         } else {
@@ -195,27 +1184,71 @@ pub fn parse_markup(input: &str) -> Result<Vec<TextElement>, String> {
                 Action::ResetStyle => {
                     style.size = None;
                     style.font = None;
+                    style.font_fallbacks = None;
                     style.is_bold = None;
                     style.is_italic = None;
                 }
                 Action::UpdateColor(color) => {
-                    style.color = Some(color);
+                    style.set_color(color, options.color_stack);
                 }
                 Action::ResetColor => {
-                    style.color = None;
+                    style.color = style.previous_colors.pop().flatten();
+                }
+                Action::UpdatePalette(palette) => {
+                    style.restarts.palette = palette.is_some();
+                    style.palette = palette;
+                }
+                Action::UpdateColorWave(wave) => {
+                    style.restarts.color_wave = wave.is_some();
+                    style.color_wave = wave;
+                }
+                Action::UpdateHue(degrees) => {
+                    style.hue_rotation = degrees;
+                }
+                Action::UpdateTimings(timings) => {
+                    style.restarts.timings = timings.is_some();
+                    style.timings = timings;
+                }
+                Action::UpdateDeco(deco) => {
+                    style.deco = deco;
+                }
+                Action::UpdateBaselineShift(shift) => {
+                    style.baseline_shift = shift;
+                }
+                Action::UpdateScale(scale) => {
+                    style.scale = scale;
+                }
+                Action::UpdateOutlineWidth(width) => {
+                    style.outline_width = width;
+                }
+                Action::UpdateLayer(layer) => {
+                    style.layer = layer;
+                }
+                Action::SplitRegion(split) => {
+                    style.split = split;
+                }
+                Action::PageBreak => {
+                    style.page_index += 1;
+                    style.line_index = 0;
+                }
+                Action::ResetAll => {
+                    // Regions are structure rather than style, so they survive.
+                    style = Style {
+                        split: style.split,
+                        line_index: style.line_index,
+                        page_index: style.page_index,
+                        ..Style::default()
+                    };
                 }
                 Action::AppendText(text, newline_count) => {
                     if !text.is_empty() {
-                        elements.push(TextElement {
-                            size: style.size,
-                            font: style.font.clone(),
-                            is_bold: style.is_bold,
-                            is_italic: style.is_italic,
-                            color: style.color.clone(),
-                            line_index: Some(style.line_index),
-                            text: text.to_string(),
-                        });
+                        elements.push(style.to_element(text));
                         style.line_index += newline_count;
+                        // Line breaks hold no glyphs, so the restart waits
+                        // for the text after them.
+                        if newline_count == 0 {
+                            style.restarts = ListRestarts::default();
+                        }
                     }
                 }
             }
@@ -223,13 +1256,6 @@ pub fn parse_markup(input: &str) -> Result<Vec<TextElement>, String> {
         },
     )
     .parse(input)
-    .map_err(|e| e.to_string())?;
-
-    if rem.is_empty() {
-        Ok(elements)
-    } else {
-        Err(format!("Unparsed input remaining: {}", rem))
-    }
 }
 
 #[cfg(test)]
@@ -253,11 +1279,11 @@ mod test {
         let result = parse_markup(input).unwrap();
         assert_eq!(result.len(), 2);
         assert_eq!(result[0].text, "italic and red");
-        assert_eq!(result[0].size, Some(12.0));
+        assert_eq!(result[0].size, Some(SizeSpec::Absolute(12.0)));
         assert_eq!(result[0].is_italic, Some(true));
         assert_eq!(result[0].color, Some("ff0000".to_string()));
         assert_eq!(result[1].text, "italic only");
-        assert_eq!(result[1].size, Some(12.0));
+        assert_eq!(result[1].size, Some(SizeSpec::Absolute(12.0)));
         assert_eq!(result[1].is_italic, Some(true));
         assert_eq!(result[1].color, None);
     }
@@ -271,7 +1297,7 @@ mod test {
         assert_eq!(elements.len(), 2);
         assert_eq!(elements[0].text, "Initial Text<other>Tag");
         assert_eq!(elements[1].text, "Hello World!");
-        assert_eq!(elements[1].size, Some(12.5));
+        assert_eq!(elements[1].size, Some(SizeSpec::Absolute(12.5)));
         assert_eq!(elements[1].color, Some("FF0000".to_string()));
     }
 
@@ -297,7 +1323,7 @@ mod test {
         let input = "<s10><#ff0000>text";
         let result = parse_markup(input).unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0].size, Some(10.0));
+        assert_eq!(result[0].size, Some(SizeSpec::Absolute(10.0)));
         assert_eq!(result[0].color, Some("ff0000".to_string()));
         assert_eq!(result[0].text, "text");
     }
@@ -310,7 +1336,7 @@ mod test {
 
         let el1 = &result[0];
         assert_eq!(el1.text, "bold, 50px, red");
-        assert_eq!(el1.size, Some(50.0));
+        assert_eq!(el1.size, Some(SizeSpec::Absolute(50.0)));
         assert_eq!(el1.font, Some("Arial".to_string()));
         assert_eq!(el1.is_bold, Some(true));
         assert_eq!(el1.color, Some("123".to_string()));
@@ -329,7 +1355,7 @@ mod test {
         let result = parse_markup(input).unwrap();
         assert_eq!(result.len(), 3);
 
-        assert_eq!(result[0].size, Some(50.0));
+        assert_eq!(result[0].size, Some(SizeSpec::Absolute(50.0)));
         assert_eq!(result[0].font, None);
 
         assert_eq!(result[1].size, None);
@@ -339,6 +1365,420 @@ mod test {
         assert_eq!(result[2].font, None);
     }
 
+    #[test]
+    fn test_color_wave() {
+        let input = "<colorfn:sine,ff0000,0000ff,period=10>ab<colorfn>c<colorfn:cos,ff0000,0000ff,period=10>";
+        let result = parse_markup(input).unwrap();
+        let wave = result[0].color_wave.as_ref().unwrap();
+        assert_eq!((wave.from.as_str(), wave.to.as_str()), ("ff0000", "0000ff"));
+        assert_eq!(wave.period, 10.0);
+        assert_eq!(result[1].color_wave, None);
+        assert!(
+            result[1]
+                .text
+                .ends_with("<colorfn:cos,ff0000,0000ff,period=10>")
+        );
+
+        assert_eq!(wave.color_at(0).as_deref(), Some("ff0000"));
+        assert_eq!(wave.color_at(5).as_deref(), Some("0000ff"));
+        // t = (1 - cos(2π·2/10)) / 2 ≈ 0.345
+        let t = (1.0 - (std::f32::consts::TAU * 0.2).cos()) / 2.0;
+        assert_eq!(wave.color_at(2), mix("ff0000", "0000ff", t));
+        assert_eq!(wave.color_at(2).as_deref(), Some("a70058"));
+    }
+
+    #[test]
+    fn test_palette_list() {
+        let input = "<palette:ff0000, 00ff00,0000ff>abc<palette>d<palette:xyz>";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            result[0].palette,
+            Some(vec![
+                "ff0000".to_string(),
+                "00ff00".to_string(),
+                "0000ff".to_string()
+            ])
+        );
+        assert_eq!(result[1].palette, None);
+        assert_eq!(result[1].text, "d<palette:xyz>");
+    }
+
+    #[test]
+    fn test_flag_warnings_for_stray_characters() {
+        let warnings = flag_warnings("<s20,,Bld>a<s,,BI I>b<s,,B I>c<scale150>d");
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("\"ld\""));
+        assert!(warnings[1].contains("I が重複"));
+    }
+
+    #[test]
+    fn test_stray_flags_are_ignored_when_lenient() {
+        let result = parse_markup("<s20,,Bld>a").unwrap();
+        assert_eq!(result[0].is_bold, Some(true));
+        assert_eq!(result[0].is_italic, Some(false));
+    }
+
+    #[test]
+    fn test_page_break() {
+        let result = parse_markup("a\\nb<page>c<reset>d").unwrap();
+        let pages: Vec<_> = result
+            .iter()
+            .map(|el| (el.text.as_str(), el.page_index, el.line_index))
+            .collect();
+        assert_eq!(
+            pages,
+            [
+                ("a", Some(0), Some(0)),
+                (NEWLINE, Some(0), Some(0)),
+                ("b", Some(0), Some(1)),
+                ("c", Some(1), Some(0)),
+                ("d", Some(1), Some(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_elements_to_pretty_json() {
+        let elements = parse_markup("<#ff0000>a").unwrap();
+        let json = elements_to_pretty_json(&elements).unwrap();
+        assert_eq!(
+            json,
+            "[\n  {\n    \"color\": \"ff0000\",\n    \"line_index\": 0,\n    \"page_index\": 0,\n    \"text\": \"a\"\n  }\n]"
+        );
+    }
+
+    #[test]
+    fn test_is_default_style() {
+        let result = parse_markup("a<page>b<#ff0000>c<#><split>d").unwrap();
+        assert!(result[0].is_default_style());
+        assert!(result[1].is_default_style());
+        assert!(!result[2].is_default_style());
+        assert!(!result[3].is_default_style());
+    }
+
+    #[test]
+    fn test_elements_to_markup_emits_minimal_tags() {
+        let elements = parse_markup("a<#ff0000>b<#ff0000>c<s20>d<reset>e").unwrap();
+        assert_eq!(
+            elements_to_markup(&elements),
+            "a<#ff0000>bc<s20,,>d<reset>e"
+        );
+        let elements = parse_markup("<split>a</split>b<page>c").unwrap();
+        assert_eq!(elements_to_markup(&elements), "<split>a</split>b<page>c");
+    }
+
+    #[test]
+    fn test_elements_to_markup_merges_near_sizes() {
+        let mut elements = parse_markup("<s12><ow2>a<ow3>b").unwrap();
+        elements[1].size = Some(SizeSpec::Absolute(12.00001));
+        elements[1].outline_width = Some(2.00001);
+        assert_eq!(elements_to_markup(&elements), "<s12,,><ow2>ab");
+        assert_eq!(
+            elements_to_markup_with(&elements, 0.0),
+            "<s12,,><ow2>a<s12.00001,,><ow2.00001>b"
+        );
+    }
+
+    #[test]
+    fn test_elements_to_markup_round_trips() {
+        let input = "<s20,\"A\"|B,I><#00ff00>a<hue120>b\n<s*1.5>c<palette:ff0000,0000ff><colorfn:sine,ff0000,0000ff,period=4>\
+                     <timing:1,2><deco:outline><y-3><scale150><ow2><layer:3>d<split>e<page><s,, >f";
+        let elements = parse_markup(input).unwrap();
+        let markup = elements_to_markup(&elements);
+        assert_eq!(parse_markup(&markup).unwrap(), elements);
+    }
+
+    #[test]
+    fn test_timing_list() {
+        let result = parse_markup("<timing:0, 3,5,10>ab<timing>c<timing:1,x>").unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].timings, Some(vec![0, 3, 5, 10]));
+        assert_eq!(result[1].timings, None);
+        assert_eq!(result[1].text, "c<timing:1,x>");
+    }
+
+    #[test]
+    fn test_explicit_base_differs_from_reset() {
+        let result = parse_markup("<s20,Arial,B><s>a<s20,Arial,B><s=>b<s=,Meiryo,>c").unwrap();
+        assert_eq!(result[0].size, None);
+        assert_eq!(result[1].size, Some(SizeSpec::Base));
+        assert_eq!(result[1].font, None);
+        assert_eq!(result[1].is_bold, None);
+        assert_eq!(result[2].size, Some(SizeSpec::Base));
+        assert_eq!(result[2].font, Some("Meiryo".to_string()));
+        for el in &result {
+            assert_eq!(el.effective_size(30.0), 30.0);
+        }
+    }
+
+    #[test]
+    fn test_auto_size() {
+        let result = parse_markup("<sauto>a<s*2>b<s>c").unwrap();
+        assert_eq!(result[0].size, Some(SizeSpec::Auto));
+        assert_eq!(result[1].size, Some(SizeSpec::Auto));
+        assert_eq!(result[2].size, None);
+        assert_eq!(result[0].effective_size(30.0), 30.0);
+    }
+
+    #[test]
+    fn test_empty_segments_clear_their_fields() {
+        let styled = |el: &TextElement| (el.size, el.font.clone(), el.is_bold, el.is_italic);
+        let set = (
+            Some(SizeSpec::Absolute(20.0)),
+            Some("Arial".to_string()),
+            Some(true),
+            Some(false),
+        );
+
+        let result = parse_markup("<s20,Arial,B>a<s,>b").unwrap();
+        assert_eq!(styled(&result[0]), set);
+        assert_eq!(styled(&result[1]), (None, None, Some(true), Some(false)));
+
+        let result = parse_markup("<s20,Arial,B>a<s,,>b").unwrap();
+        assert_eq!(styled(&result[1]), (None, None, None, None));
+
+        let result = parse_markup("<s20,Arial,B>a<s,,,>b").unwrap();
+        assert_eq!(styled(&result[1]), (None, None, None, None));
+        assert_eq!(result[1].text, "b");
+    }
+
+    #[test]
+    fn test_multiplier_after_explicit_base() {
+        let result = parse_markup("<s=><s*2>a").unwrap();
+        assert_eq!(result[0].size, Some(SizeSpec::Multiplier(2.0)));
+        assert_eq!(result[0].effective_size(30.0), 60.0);
+    }
+
+    #[test]
+    fn test_hue_rotation() {
+        let result = parse_markup("<#ff0000><hue180>a<hue>b<#>c<hue90>d").unwrap();
+        assert_eq!(result[0].color, Some("00ffff".to_string()));
+        assert_eq!(result[0].hue_rotation, Some(180.0));
+        assert_eq!(result[1].color, Some("ff0000".to_string()));
+        assert_eq!(result[1].hue_rotation, None);
+        assert_eq!(result[2].color, None);
+        assert_eq!(result[3].color, None);
+        assert_eq!(result[3].hue_rotation, Some(90.0));
+    }
+
+    #[test]
+    fn test_full_width_digits_around_style_tag() {
+        let result = parse_markup("１２<s20>３４<s>５").unwrap();
+        let runs: Vec<_> = result
+            .iter()
+            .map(|el| (el.text.as_str(), el.size))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("１２", None),
+                ("３４", Some(SizeSpec::Absolute(20.0))),
+                ("５", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_style_tag_color_shorthand() {
+        let result = parse_markup("<s20>a<s#f00>b<s20,Arial,B>c<s#0f0,Meiryo,>d<s#xyz>").unwrap();
+        let runs: Vec<_> = result
+            .iter()
+            .map(|el| {
+                (
+                    el.text.as_str(),
+                    el.size,
+                    el.font.as_deref(),
+                    el.color.as_deref(),
+                )
+            })
+            .collect();
+        let size = Some(SizeSpec::Absolute(20.0));
+        assert_eq!(
+            runs,
+            [
+                ("a", size, None, None),
+                ("b", size, None, Some("f00")),
+                ("c", size, Some("Arial"), Some("f00")),
+                ("d<s#xyz>", size, Some("Meiryo"), Some("0f0")),
+            ]
+        );
+        assert_eq!(result[2].is_bold, Some(true));
+        assert_eq!(result[3].is_bold, None);
+    }
+
+    #[test]
+    fn test_full_width_size_is_not_a_tag() {
+        let result = parse_markup("a<s２０>b").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "a<s２０>b");
+        assert_eq!(result[0].size, None);
+    }
+
+    #[test]
+    fn test_color_stack_restores_previous_color() {
+        let input = "<#f00><#0f0>a<#>b<#>c";
+        let options = ParseOptions {
+            color_stack: true,
+            ..ParseOptions::default()
+        };
+        let colors: Vec<_> = parse_markup_with(input, &options)
+            .unwrap()
+            .into_iter()
+            .map(|el| el.color)
+            .collect();
+        assert_eq!(
+            colors,
+            [Some("0f0".to_string()), Some("f00".to_string()), None]
+        );
+
+        let colors: Vec<_> = parse_markup(input)
+            .unwrap()
+            .into_iter()
+            .map(|el| el.color)
+            .collect();
+        assert_eq!(colors, [Some("0f0".to_string()), None, None]);
+    }
+
+    #[test]
+    fn test_text_scan_stops_at_unescaped_opener() {
+        assert_eq!(
+            parse_text_greedy("a\\<s20>b<#f00>c"),
+            Ok(("\\<s20>b<#f00>c", "a"))
+        );
+        assert_eq!(parse_text_greedy("s20>b<#f00>c"), Ok(("<#f00>c", "s20>b")));
+        assert_eq!(parse_text_greedy("b<x>\\<#>"), Ok(("\\<#>", "b<x>")));
+
+        let result = parse_markup("a\\<s20>b<#f00>c\\<#>d<#>e").unwrap();
+        let runs: Vec<_> = result
+            .iter()
+            .map(|el| (el.text.as_str(), el.size, el.color.as_deref()))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("a", None, None),
+                ("<", None, None),
+                ("s20>b", None, None),
+                ("c", None, Some("f00")),
+                ("<", None, Some("f00")),
+                ("#>d", None, Some("f00")),
+                ("e", None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_escaped_opener_survives_unknown_tag_policy() {
+        let options = ParseOptions {
+            unknown_tags: UnknownTagPolicy::Drop,
+            ..ParseOptions::default()
+        };
+        let result = parse_markup_with("\\<other>a<other>", &options).unwrap();
+        let texts: Vec<_> = result.iter().map(|el| el.text.as_str()).collect();
+        assert_eq!(texts, ["<", "other>a"]);
+    }
+
+    #[test]
+    fn test_flag_aliases() {
+        let options = ParseOptions {
+            flag_aliases: [('太', 'B'), ('斜', 'I')].into(),
+            ..ParseOptions::default()
+        };
+        let result = parse_markup_with("<s20,,太>a<s,,斜B>b<s,太,>太", &options).unwrap();
+        let runs: Vec<_> = result
+            .iter()
+            .map(|el| {
+                (
+                    el.text.as_str(),
+                    el.is_bold,
+                    el.is_italic,
+                    el.font.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("a", Some(true), Some(false), None),
+                ("b", Some(true), Some(true), None),
+                ("太", None, None, Some("太")),
+            ]
+        );
+
+        let result = parse_markup("<s20,,太>a").unwrap();
+        assert_eq!(result[0].is_bold, Some(false));
+
+        // Escaped tags are text and keep their letters.
+        let result = parse_markup_with("\\<s20,,太>a", &options).unwrap();
+        let text: String = result.iter().map(|el| el.text.as_str()).collect();
+        assert_eq!(text, "<s20,,太>a");
+        assert!(flag_diagnostics("\\<s20,,X>a", &options.flag_aliases).is_empty());
+        assert!(flag_diagnostics("<s20,,太>a", &options.flag_aliases).is_empty());
+    }
+
+    fn parse_with_policy(policy: UnknownTagPolicy) -> Result<Vec<TextElement>, String> {
+        let options = ParseOptions {
+            unknown_tags: policy,
+            ..ParseOptions::default()
+        };
+        parse_markup_with("a<other>b<#ff0000>c<d", &options)
+    }
+
+    #[test]
+    fn test_unknown_tag_policy_keep() {
+        let result = parse_with_policy(UnknownTagPolicy::Keep).unwrap();
+        assert_eq!(result[0].text, "a<other>b");
+        assert_eq!(result[1].text, "c<d");
+    }
+
+    #[test]
+    fn test_unknown_tag_policy_drop() {
+        let result = parse_with_policy(UnknownTagPolicy::Drop).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].text, "ab");
+        assert_eq!(result[1].text, "c<d");
+        assert_eq!(result[1].color, Some("ff0000".to_string()));
+    }
+
+    #[test]
+    fn test_unknown_tag_policy_error() {
+        let error = parse_with_policy(UnknownTagPolicy::Error).unwrap_err();
+        assert_eq!(error, "Unknown tag <other> at byte 1");
+    }
+
+    #[test]
+    fn test_lenient_parse_reports_diagnostics() {
+        let input = "a<other>b<s20,,X>c";
+        let (elements, diagnostics) = parse_markup_lenient(input);
+        assert_eq!(elements, parse_markup(input).unwrap());
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].position, 1);
+        assert_eq!(diagnostics[0].message, "Unknown tag <other>");
+        assert_eq!(diagnostics[1].position, 9);
+        assert_eq!(diagnostics[1].message, flag_warnings(input)[0]);
+    }
+
+    #[test]
+    fn test_lenient_parse_clean_input() {
+        let (elements, diagnostics) = parse_markup_lenient("<#f00>ab");
+        assert_eq!(elements, parse_markup("<#f00>ab").unwrap());
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_font_fallback_list() {
+        let input = "<s,\"Noto Sans JP\"|\"Arial\",>abc<s,Meiryo,>def<s>ghi";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].font, Some("Noto Sans JP".to_string()));
+        assert_eq!(result[0].font_fallbacks, Some(vec!["Arial".to_string()]));
+        assert_eq!(result[1].font, Some("Meiryo".to_string()));
+        assert_eq!(result[1].font_fallbacks, None);
+        assert_eq!(result[2].font, None);
+        assert_eq!(result[2].font_fallbacks, None);
+    }
+
     #[test]
     fn test_line_index_collection() {
         let input = "Line 0\\nLine 1<#ff0000>Red Line 1\\nLine 2<s>Reset Line 2";
@@ -351,8 +1791,8 @@ mod test {
         assert_eq!(result[0].line_index, Some(0));
         assert_eq!(result[0].color, None);
 
-        // Element 1: "\\n"
-        assert_eq!(result[1].text, "\\n");
+        // Element 1: newline
+        assert_eq!(result[1].text, NEWLINE);
         assert_eq!(result[1].line_index, Some(0));
         assert_eq!(result[1].color, None);
 
@@ -366,8 +1806,8 @@ mod test {
         assert_eq!(result[3].line_index, Some(1));
         assert_eq!(result[3].color, Some("ff0000".to_string()));
 
-        // Element 4: "\\n" (color carry-over)
-        assert_eq!(result[4].text, "\\n");
+        // Element 4: newline (color carry-over)
+        assert_eq!(result[4].text, NEWLINE);
         assert_eq!(result[4].line_index, Some(1));
         assert_eq!(result[4].color, Some("ff0000".to_string()));
 
@@ -386,4 +1826,253 @@ mod test {
         // The input string has no further text after "Reset Line 2".
         // The last element is "Reset Line 2".
     }
+
+    #[test]
+    fn test_plain_text_fast_path_matches_full_parser() {
+        for input in ["plain text", "テキスト", "a > b", ""] {
+            assert_eq!(
+                parse_markup_with_end(input, &ParseOptions::default()),
+                parse_markup_full(input, &ParseOptions::default()),
+                "{:?}",
+                input
+            );
+        }
+        let result = parse_markup("plain text").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "plain text");
+        assert_eq!(result[0].line_index, Some(0));
+        assert_eq!(result[0].size, None);
+        assert_eq!(result[0].color, None);
+    }
+
+    #[test]
+    fn test_size_multiplier_after_explicit_size() {
+        let input = "<s20>normal<s*2>double";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].size, Some(SizeSpec::Absolute(20.0)));
+        assert_eq!(result[1].size, Some(SizeSpec::Absolute(40.0)));
+        assert_eq!(result[1].effective_size(30.0), 40.0);
+    }
+
+    #[test]
+    fn test_size_multiplier_without_size_uses_base() {
+        let input = "<s*2>double";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].size, Some(SizeSpec::Multiplier(2.0)));
+        assert_eq!(result[0].effective_size(30.0), 60.0);
+    }
+
+    #[test]
+    fn test_reset_all_clears_style_and_color() {
+        for input in [
+            "<s20,Arial,B><#f00>styled<reset>plain",
+            "<s20,Arial,B><#f00>styled</>plain",
+        ] {
+            let result = parse_markup(input).unwrap();
+            assert_eq!(result.len(), 2);
+            assert_eq!(result[0].font, Some("Arial".to_string()));
+            assert_eq!(result[0].color, Some("f00".to_string()));
+            let el = &result[1];
+            assert_eq!(el.text, "plain");
+            assert_eq!(el.size, None);
+            assert_eq!(el.font, None);
+            assert_eq!(el.is_bold, None);
+            assert_eq!(el.is_italic, None);
+            assert_eq!(el.color, None);
+        }
+    }
+
+    #[test]
+    fn test_unknown_reset_variation_is_text() {
+        let result = parse_markup("a<resets>b").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "a<resets>b");
+    }
+
+    #[test]
+    fn test_decoration_tag() {
+        let input = "plain<deco:outline>outlined<deco:影付き文字>shadowed<deco>default";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].deco, None);
+        assert_eq!(result[1].deco, Some("縁取り文字".to_string()));
+        assert_eq!(result[2].deco, Some("影付き文字".to_string()));
+        assert_eq!(result[3].deco, None);
+    }
+
+    #[test]
+    fn test_unknown_decoration_is_text() {
+        let input = "a<deco:sparkle>b";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "a<deco:sparkle>b");
+        assert_eq!(result[0].deco, None);
+    }
+
+    #[test]
+    fn test_baseline_shift_tag() {
+        let input = "a<y-5>b<y2.5>c<y>d<yes>";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].baseline_shift, None);
+        assert_eq!(result[1].baseline_shift, Some(-5.0));
+        assert_eq!(result[2].baseline_shift, Some(2.5));
+        assert_eq!(result[3].baseline_shift, None);
+        assert_eq!(result[3].text, "d<yes>");
+    }
+
+    #[test]
+    fn test_literal_and_real_newlines_are_equivalent() {
+        let escaped = parse_markup("a\\nb<#f00>c\\nd").unwrap();
+        let real = parse_markup("a\nb<#f00>c\nd").unwrap();
+        assert_eq!(escaped, real);
+        assert_eq!(escaped[1].text, NEWLINE);
+        assert_eq!(escaped[5].line_index, Some(2));
+    }
+
+    #[test]
+    fn test_literal_tab_escape() {
+        let result = parse_markup("a\\tb").unwrap();
+        let text: String = result.iter().map(|el| el.text.as_str()).collect();
+        assert_eq!(text, "a\tb");
+    }
+
+    #[test]
+    fn test_crlf_and_lone_cr_are_single_breaks() {
+        let crlf = parse_markup("ab\r\ncd\re").unwrap();
+        let lf = parse_markup("ab\ncd\ne").unwrap();
+        assert_eq!(crlf, lf);
+        assert!(crlf.iter().all(|el| !el.text.contains('\r')));
+        assert_eq!(crlf.last().unwrap().line_index, Some(2));
+    }
+
+    #[test]
+    fn test_scale_tag() {
+        let input = "<s20>a<scale150>b<scale>c";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].scale, None);
+        assert_eq!(result[1].scale, Some(150.0));
+        assert_eq!(result[1].size, Some(SizeSpec::Absolute(20.0)));
+        assert_eq!(result[2].scale, None);
+        assert_eq!(result[2].size, Some(SizeSpec::Absolute(20.0)));
+    }
+
+    #[test]
+    fn test_custom_newline_marker() {
+        let options = ParseOptions {
+            newline_marker: Some("{{br}}".to_string()),
+            ..ParseOptions::default()
+        };
+        let result = parse_markup_with("ab{{br}}<#f00>c\nd", &options).unwrap();
+        assert_eq!(result, parse_markup("ab\n<#f00>c\nd").unwrap());
+        assert_eq!(result.last().unwrap().line_index, Some(2));
+
+        let result = parse_markup("ab{{br}}c").unwrap();
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn test_outline_width_tag() {
+        let input = "a<ow3>b<ow0.5>c<ow>d";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].outline_width, None);
+        assert_eq!(result[1].outline_width, Some(3.0));
+        assert_eq!(result[2].outline_width, Some(0.5));
+        assert_eq!(result[3].outline_width, None);
+    }
+
+    #[test]
+    fn test_layer_tag() {
+        let result = parse_markup("a<layer:5>b<layer>c<layer:0>d<layer:x>").unwrap();
+        assert_eq!(result[0].layer, None);
+        assert_eq!(result[1].layer, Some(5));
+        assert_eq!(result[2].layer, None);
+        assert_eq!(result[2].text, "c<layer:0>d<layer:x>");
+    }
+
+    #[test]
+    fn test_invalid_outline_width_is_text() {
+        let result = parse_markup("a<ow-1>b<owx>c").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "a<ow-1>b<owx>c");
+    }
+
+    #[test]
+    fn test_unclosed_tag_stays_local() {
+        let result = parse_markup("a<s20<#ff0000>b<s30,Arial<s12>c").unwrap();
+        let runs: Vec<_> = result
+            .iter()
+            .map(|el| (el.text.as_str(), el.color.as_deref(), el.size))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("a<s20", None, None),
+                ("b<s30,Arial", Some("ff0000"), None),
+                ("c", Some("ff0000"), Some(SizeSpec::Absolute(12.0))),
+            ]
+        );
+        let result = parse_markup("<s20\n<#00ff00>x").unwrap();
+        assert_eq!(result[0].text, "<s20");
+        assert_eq!(result[2].color.as_deref(), Some("00ff00"));
+    }
+
+    #[test]
+    fn test_style_at() {
+        let input = "a<s20>bc<#ff0000>d<reset>e\nf";
+        let style = |index| {
+            let el = style_at(input, index);
+            (el.size, el.color, el.line_index)
+        };
+        let size = Some(SizeSpec::Absolute(20.0));
+        let red = Some("ff0000".to_string());
+        assert_eq!(style(0), (None, None, Some(0)));
+        // At and inside a tag, it hasn't applied yet.
+        assert_eq!(style(1), (None, None, Some(0)));
+        assert_eq!(style(3), (None, None, Some(0)));
+        assert_eq!(style(6), (size, None, Some(0)));
+        assert_eq!(style(7), (size, None, Some(0)));
+        assert_eq!(style(12), (size, None, Some(0)));
+        assert_eq!(style(17), (size, red, Some(0)));
+        assert_eq!(style(25), (None, None, Some(0)));
+        assert_eq!(style(28), (None, None, Some(1)));
+        assert_eq!(style(100), style(28));
+        assert_eq!(style_at(input, 6).text, "");
+    }
+
+    #[test]
+    fn test_split_region_markers() {
+        let input = "ab<split>c<reset>d</split>e";
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].split, None);
+        assert_eq!(result[1].split, Some(true));
+        assert_eq!(result[2].split, Some(true));
+        assert_eq!(result[3].split, None);
+
+        let result = parse_markup("a<nosplit>bc</nosplit>d").unwrap();
+        let splits: Vec<_> = result.iter().map(|el| el.split).collect();
+        assert_eq!(splits, [None, Some(false), None]);
+        assert_eq!(result[1].text, "bc");
+        assert_eq!(elements_to_markup(&result), "a<nosplit>bc</nosplit>d");
+    }
+
+    #[test]
+    fn test_nfc_normalization() {
+        let input = "<#f00>e\u{301}か\u{3099}";
+        let options = ParseOptions {
+            normalize_nfc: true,
+            ..ParseOptions::default()
+        };
+        let result = parse_markup_with(input, &options).unwrap();
+        assert_eq!(result[0].text, "\u{e9}\u{304c}");
+        assert_eq!(result[0].text.chars().count(), 2);
+
+        let result = parse_markup(input).unwrap();
+        assert_eq!(result[0].text, "e\u{301}か\u{3099}");
+    }
 }
@@ -0,0 +1,293 @@
+use crate::error::SplitError;
+use std::{collections::HashMap, fmt};
+
+/// Highest layer probed before giving up, so a host that rejects every
+/// creation can't keep the search going forever.
+pub const MAX_LAYER: usize = 1000;
+
+/// Default for the failed creations in a row after which the host is
+/// assumed to reject creation altogether rather than the layers being
+/// occupied.
+pub const MAX_CONSECUTIVE_FAILURES: usize = 32;
+
+/// Layers known to be taken from earlier probes of one split, so that later
+/// glyphs skip them instead of asking the host again from the same base.
+#[derive(Debug, Default)]
+pub struct ProbeMemory {
+    /// Frame ranges this split has placed objects on, by layer.
+    placed: HashMap<usize, Vec<(usize, usize)>>,
+    /// Frame ranges a creation failed for, by layer. Something overlaps
+    /// them, so any range containing one fails too.
+    failed: HashMap<usize, Vec<(usize, usize)>>,
+}
+
+impl ProbeMemory {
+    fn is_taken(&self, layer: usize, (start, end): (usize, usize)) -> bool {
+        let overlaps = |&(s, e): &(usize, usize)| s < end && start < e;
+        let contained = |&(s, e): &(usize, usize)| start <= s && e <= end;
+        self.placed
+            .get(&layer)
+            .is_some_and(|ranges| ranges.iter().any(overlaps))
+            || self
+                .failed
+                .get(&layer)
+                .is_some_and(|ranges| ranges.iter().any(contained))
+    }
+}
+
+/// Tries `try_create` on `layer` and every layer above it until one succeeds,
+/// returning the layer the object was actually placed on. Layers `memory`
+/// knows to be taken for `frames`, a `(start, length)` pair, are skipped
+/// without a call, and `max_attempts` failed calls in a row give up; at
+/// least one call is always made.
+pub fn place_incremental<E: fmt::Display>(
+    layer: usize,
+    (frame, length): (usize, usize),
+    memory: &mut ProbeMemory,
+    max_attempts: usize,
+    try_create: &mut impl FnMut(usize) -> Result<(), E>,
+) -> Result<usize, SplitError> {
+    let range = (frame, frame + length);
+    let max_attempts = max_attempts.max(1);
+    let mut first_error = None;
+    let mut attempts = 0;
+    for target in layer.. {
+        if target > MAX_LAYER {
+            return Err(SplitError::LayerOverflow { layer: target });
+        }
+        if memory.is_taken(target, range) {
+            continue;
+        }
+        if attempts == max_attempts {
+            let message = first_error.unwrap_or_default();
+            aviutl2::log::warn!("最初の作成エラー: {}", message);
+            return Err(SplitError::CreateFailed {
+                layer,
+                attempts,
+                message,
+            });
+        }
+        attempts += 1;
+        match try_create(target) {
+            Ok(()) => {
+                memory.placed.entry(target).or_default().push(range);
+                return Ok(target);
+            }
+            Err(e) => {
+                memory.failed.entry(target).or_default().push(range);
+                first_error.get_or_insert_with(|| e.to_string());
+            }
+        }
+    }
+    unreachable!("the layer range is unbounded")
+}
+
+/// Packs objects given as `(frame, length)` onto as few layers from
+/// `first_layer` up as possible, putting each on the lowest layer that is
+/// free for its whole range by then. Returns the layer of every object.
+pub fn compact_layers(ranges: &[(usize, usize)], first_layer: usize) -> Vec<usize> {
+    // Frame at which each layer becomes free again.
+    let mut free_from: Vec<usize> = Vec::new();
+    ranges
+        .iter()
+        .map(|&(frame, length)| {
+            let index = match free_from.iter().position(|&free| free <= frame) {
+                Some(index) => index,
+                None => {
+                    free_from.push(0);
+                    free_from.len() - 1
+                }
+            };
+            free_from[index] = frame + length;
+            first_layer + index
+        })
+        .collect()
+}
+
+/// Tracks how far collisions have pushed the glyphs of one source object so
+/// that the following glyphs continue above the bumped one.
+#[derive(Debug, Default)]
+pub struct LayerCursor {
+    shift: usize,
+    prev_planned: Option<usize>,
+}
+
+impl LayerCursor {
+    /// Returns the layer to try first for a glyph planned on `planned`.
+    /// A planned layer that doesn't follow the previous one starts a new
+    /// block, which resets the accumulated shift.
+    ///
+    /// Within a block, the shift carries every object above the one placed
+    /// before it, so the higher of two objects drawn on the same spot, like
+    /// a fill over its stroke copy, stays on top however they were bumped.
+    pub fn target(&mut self, planned: usize) -> usize {
+        if self.prev_planned.is_none_or(|prev| planned != prev + 1) {
+            self.shift = 0;
+        }
+        self.prev_planned = Some(planned);
+        planned + self.shift
+    }
+
+    pub fn placed(&mut self, target: usize, actual: usize) {
+        self.shift += actual.saturating_sub(target);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn place<E: fmt::Display>(
+        layer: usize,
+        try_create: &mut impl FnMut(usize) -> Result<(), E>,
+    ) -> Result<usize, SplitError> {
+        let mut memory = ProbeMemory::default();
+        place_incremental(
+            layer,
+            (0, 60),
+            &mut memory,
+            MAX_CONSECUTIVE_FAILURES,
+            try_create,
+        )
+    }
+
+    #[test]
+    fn test_place_incremental_skips_occupied_layers() {
+        let occupied = [3, 4];
+        let placed = place(3, &mut |layer| free(&occupied, layer));
+        assert_eq!(placed, Ok(5));
+    }
+
+    fn free(occupied: &[usize], layer: usize) -> Result<(), String> {
+        if occupied.contains(&layer) {
+            Err(format!("layer {} is occupied", layer))
+        } else {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_place_incremental_overflow() {
+        let placed = place(MAX_LAYER - 1, &mut |_| Err("occupied"));
+        assert_eq!(
+            placed,
+            Err(SplitError::LayerOverflow {
+                layer: MAX_LAYER + 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_place_incremental_aborts_when_creation_keeps_failing() {
+        let mut calls = 0;
+        let placed = place(3, &mut |layer| {
+            calls += 1;
+            Err(format!("unsupported alias on layer {}", layer))
+        });
+        assert_eq!(calls, MAX_CONSECUTIVE_FAILURES);
+        assert_eq!(
+            placed,
+            Err(SplitError::CreateFailed {
+                layer: 3,
+                attempts: MAX_CONSECUTIVE_FAILURES,
+                message: "unsupported alias on layer 3".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_memory_reduces_probes_on_dense_timeline() {
+        // Layers 4 to 40 are full; three source objects each put three
+        // glyphs on 4, 5 and 6 over the same frames.
+        let probes = |remember: bool| {
+            let mut taken: Vec<usize> = (4..=40).collect();
+            let mut memory = ProbeMemory::default();
+            let mut calls = 0;
+            for _ in 0..3 {
+                let mut cursor = LayerCursor::default();
+                for planned in [4, 5, 6] {
+                    if !remember {
+                        memory = ProbeMemory::default();
+                    }
+                    let target = cursor.target(planned);
+                    let placed =
+                        place_incremental(target, (0, 60), &mut memory, 100, &mut |layer| {
+                            calls += 1;
+                            free(&taken, layer)?;
+                            taken.push(layer);
+                            Ok::<(), String>(())
+                        })
+                        .unwrap();
+                    cursor.placed(target, placed);
+                }
+            }
+            (calls, taken.split_off(37))
+        };
+
+        let (calls, layers) = probes(true);
+        assert_eq!(layers, (41..=49).collect::<Vec<_>>());
+        assert_eq!(calls, 9 + 37);
+        let (linear_calls, linear_layers) = probes(false);
+        assert_eq!(linear_layers, layers);
+        assert!(linear_calls > 2 * calls);
+    }
+
+    #[test]
+    fn test_zero_attempts_still_tries_once() {
+        let mut memory = ProbeMemory::default();
+        let placed = place_incremental(3, (0, 60), &mut memory, 0, &mut |layer| free(&[], layer));
+        assert_eq!(placed, Ok(3));
+    }
+
+    #[test]
+    fn test_failure_memory_only_covers_containing_ranges() {
+        let mut memory = ProbeMemory::default();
+        let _ = place_incremental(3, (10, 20), &mut memory, 1, &mut |_| Err("occupied"));
+        assert!(memory.is_taken(3, (0, 60)));
+        assert!(!memory.is_taken(3, (15, 60)));
+    }
+
+    #[test]
+    fn test_compact_layers_reuses_finished_layers() {
+        let layers = compact_layers(&[(0, 10), (10, 5), (5, 10), (20, 5)], 3);
+        assert_eq!(layers, vec![3, 3, 4, 3]);
+    }
+
+    #[test]
+    fn test_cursor_follows_collision() {
+        let occupied = [3];
+        let mut cursor = LayerCursor::default();
+        let mut layers = Vec::new();
+        for planned in [2, 3, 4] {
+            let target = cursor.target(planned);
+            let placed = place(target, &mut |layer| free(&occupied, layer)).unwrap();
+            cursor.placed(target, placed);
+            layers.push(placed);
+        }
+        assert_eq!(layers, vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn test_cursor_keeps_pairs_in_order() {
+        // Stroke and fill copies planned on 4/5 and 6/7.
+        let occupied = [5, 6];
+        let mut cursor = LayerCursor::default();
+        let mut layers = Vec::new();
+        for planned in [4, 5, 6, 7] {
+            let target = cursor.target(planned);
+            let placed = place(target, &mut |layer| free(&occupied, layer)).unwrap();
+            cursor.placed(target, placed);
+            layers.push(placed);
+        }
+        assert_eq!(layers, vec![4, 7, 8, 9]);
+    }
+
+    #[test]
+    fn test_cursor_resets_for_new_block() {
+        let mut cursor = LayerCursor::default();
+        let target = cursor.target(2);
+        cursor.placed(target, 4);
+        assert_eq!(cursor.target(3), 5);
+        assert_eq!(cursor.target(1), 1);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{
+    config::GlyphOrigin,
+    entry::anchor_offset,
+    layout::{LayoutParams, place_glyphs_with},
+    metrics::GlyphMetrics,
+    params::{DRAW_EFFECT, TEXT_EFFECT, track_start},
+    parser::{
+        alignment::TextAlignment,
+        markup::{NEWLINE, SizeSpec, TextElement},
+    },
+};
+
+/// A glyph object created by an earlier split, read back from the timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphObject {
+    pub text: String,
+    pub font: String,
+    pub size: f32,
+    pub kern: f32,
+    /// The 標準描画 X and Y, i.e. where the glyph is anchored.
+    pub x: f32,
+    pub y: f32,
+}
+
+impl GlyphObject {
+    /// Reads the items of a glyph object through `read(effect, item)`.
+    /// `None` for objects without text, such as group controls.
+    pub fn read(read: impl Fn(&str, &str) -> Option<String>) -> Option<GlyphObject> {
+        let number = |effect: &str, item: &str| track_start(&read(effect, item)?).parse().ok();
+        Some(GlyphObject {
+            text: read(TEXT_EFFECT, "テキスト").filter(|text| !text.is_empty())?,
+            font: read(TEXT_EFFECT, "フォント")?,
+            size: number(TEXT_EFFECT, "サイズ")?,
+            kern: number(TEXT_EFFECT, "字間").unwrap_or(0.0),
+            x: number(DRAW_EFFECT, "X")?,
+            y: number(DRAW_EFFECT, "Y")?,
+        })
+    }
+}
+
+/// Splits `glyphs`, given in reading order, into lines: a glyph that isn't
+/// right of the one before it starts a new line.
+fn line_starts(glyphs: &[GlyphObject]) -> Vec<usize> {
+    (1..glyphs.len())
+        .filter(|&i| glyphs[i].x <= glyphs[i - 1].x)
+        .collect()
+}
+
+/// New anchor positions for `glyphs`, given in reading order, laid out again
+/// with `kern` and `lnsp`. The first glyph stays where it is. Unset spacing
+/// keeps the current one: the first glyph's 字間, and the line spacing
+/// between the first two lines.
+pub fn relayout(
+    glyphs: &[GlyphObject],
+    kern: Option<f32>,
+    lnsp: Option<f32>,
+    origin: GlyphOrigin,
+    metrics: &dyn GlyphMetrics,
+) -> Vec<(f32, f32)> {
+    let Some(first) = glyphs.first() else {
+        return Vec::new();
+    };
+    let starts = line_starts(glyphs);
+    let current_lnsp = starts
+        .first()
+        .map_or(0.0, |&i| glyphs[i].y - first.y - first.size);
+
+    let mut elements = Vec::new();
+    let mut line = 0;
+    for (i, glyph) in glyphs.iter().enumerate() {
+        if starts.contains(&i) {
+            elements.push(TextElement {
+                line_index: Some(line),
+                text: NEWLINE.to_string(),
+                ..TextElement::default()
+            });
+            line += 1;
+        }
+        // Kept whole, so every object stays one glyph even if it holds a
+        // word.
+        elements.push(TextElement {
+            size: Some(SizeSpec::Absolute(glyph.size)),
+            font: Some(glyph.font.clone()),
+            split: Some(false),
+            line_index: Some(line),
+            text: glyph.text.clone(),
+            ..TextElement::default()
+        });
+    }
+    let params = LayoutParams {
+        x: 0.0,
+        y: 0.0,
+        size: first.size,
+        kern: kern.unwrap_or(first.kern),
+        lnsp: lnsp.unwrap_or(current_lnsp),
+        min_size: None,
+        max_size: None,
+        line_height: None,
+    };
+    let placed = place_glyphs_with(&elements, &params, &TextAlignment::default(), metrics);
+    let anchors: Vec<_> = placed
+        .iter()
+        .map(|glyph| {
+            let (ax, ay) = anchor_offset(glyph, origin);
+            (glyph.x + ax, glyph.y + ay)
+        })
+        .collect();
+    let (dx, dy) = (first.x - anchors[0].0, first.y - anchors[0].1);
+    anchors.into_iter().map(|(x, y)| (x + dx, y + dy)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metrics::Approximate;
+
+    fn glyph(text: &str, x: f32, y: f32) -> GlyphObject {
+        GlyphObject {
+            text: text.to_string(),
+            font: "MS Gothic".to_string(),
+            size: 30.0,
+            kern: 2.0,
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn test_relayout_keeps_current_spacing() {
+        let glyphs = [
+            glyph("a", 100.0, 50.0),
+            glyph("b", 132.0, 50.0),
+            glyph("c", 100.0, 84.0),
+        ];
+        let positions = relayout(&glyphs, None, None, GlyphOrigin::TopLeft, &Approximate);
+        assert_eq!(positions, [(100.0, 50.0), (132.0, 50.0), (100.0, 84.0)]);
+    }
+
+    #[test]
+    fn test_relayout_applies_new_spacing() {
+        let glyphs = [
+            glyph("a", 115.0, 65.0),
+            glyph("b", 147.0, 65.0),
+            glyph("c", 115.0, 95.0),
+        ];
+        let positions = relayout(
+            &glyphs,
+            Some(10.0),
+            Some(6.0),
+            GlyphOrigin::Center,
+            &Approximate,
+        );
+        assert_eq!(positions, [(115.0, 65.0), (155.0, 65.0), (115.0, 101.0)]);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{error::SplitError, timeline::Timeline};
+
+/// A selected object together with its position on the timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selected<H> {
+    pub handle: H,
+    pub layer: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Orders the selection by `(layer, start)` so processing, and therefore
+/// layer allocation, doesn't depend on the order the host reports it in.
+pub fn sort_selection<H>(selection: &mut [Selected<H>]) {
+    selection.sort_by_key(|selected| (selected.layer, selected.start));
+}
+
+/// Fails with `SplitError::NoSelection` when nothing is selected, so the
+/// menu reports it instead of silently doing nothing.
+pub fn check_selection<H>(selection: &[H]) -> Result<(), SplitError> {
+    if selection.is_empty() {
+        return Err(SplitError::NoSelection);
+    }
+    Ok(())
+}
+
+/// Reads where every selected object sits, failing when nothing is
+/// selected, and returns them in the order `sort_selection` gives.
+pub fn collect_selection<T: Timeline>(
+    timeline: &T,
+) -> Result<Vec<Selected<T::Handle>>, SplitError> {
+    let mut selection = Vec::new();
+    for handle in timeline.selected_objects()? {
+        let (layer, start, end) = timeline.layer_frame(&handle)?;
+        selection.push(Selected {
+            handle,
+            layer,
+            start,
+            end,
+        });
+    }
+    check_selection(&selection)?;
+    sort_selection(&mut selection);
+    Ok(selection)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn selected(handle: &'static str, layer: usize, start: usize) -> Selected<&'static str> {
+        Selected {
+            handle,
+            layer,
+            start,
+            end: start + 10,
+        }
+    }
+
+    #[test]
+    fn test_sort_selection_by_layer_then_start() {
+        let mut selection = vec![
+            selected("c", 5, 0),
+            selected("b", 2, 30),
+            selected("a", 2, 10),
+            selected("d", 7, 0),
+        ];
+        sort_selection(&mut selection);
+        let order: Vec<_> = selection.iter().map(|s| s.handle).collect();
+        assert_eq!(order, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_empty_selection_is_reported() {
+        let empty: Vec<Selected<&str>> = Vec::new();
+        assert_eq!(check_selection(&empty), Err(SplitError::NoSelection));
+        assert_eq!(check_selection(&[selected("a", 0, 0)]), Ok(()));
+    }
+}
@@ -0,0 +1,1192 @@
+use crate::{
+    config::{LayerPolicy, SplitConfig},
+    entry::{AliasContext, MAX_ALIAS_BYTES, render_glyph_alias, render_group_alias, resolve_font},
+    error::SplitError,
+    layout::{
+        LayoutParams, PlacedGlyph, fit_auto_sizes, group_chunks, group_fixed_chunks, group_words,
+        measure_text_with, place_glyphs_with, reverse_lines, scale_glyphs, trim_lines,
+    },
+    metrics::{GlyphMetrics, Monospace, metrics_for},
+    params::{DrawParams, TextParams},
+    parser::{
+        alignment::{TextAlignment, take_alignment_directive},
+        markup::{TextElement, parse_markup_with_end},
+    },
+    placement::{MAX_LAYER, compact_layers},
+    timing::{Timing, clamp_onset, page_range},
+};
+use std::collections::{HashMap, HashSet};
+
+/// The first `count` layers from `first` up that each start `stride`
+/// adjacent layers none of which is in `claimed`, one after another.
+fn free_layers(first: usize, stride: usize, claimed: &HashSet<usize>, count: usize) -> Vec<usize> {
+    let mut layers = Vec::with_capacity(count);
+    let mut layer = first;
+    while layers.len() < count {
+        if (layer..layer + stride).any(|l| claimed.contains(&l)) {
+            layer += 1;
+        } else {
+            layers.push(layer);
+            layer += stride;
+        }
+    }
+    layers
+}
+
+/// `(alias, layer, frame, length)` for one object to create.
+pub type CreationInfo = (String, usize, usize, usize);
+
+/// Everything read from one source object besides its text and alignment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitParams {
+    pub text: TextParams,
+    pub draw: DrawParams,
+    pub layer: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// What each created object holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitUnit {
+    Glyph,
+    /// One object per word, e.g. for karaoke where `<timing>` gives each
+    /// word's highlight onset.
+    Word,
+    /// Every line cut into this many roughly equal objects.
+    Chunks(usize),
+    /// Every line cut into objects of this many glyphs, e.g. pairs animated
+    /// together.
+    FixedChunks(usize),
+}
+
+/// How a split is performed, as chosen by the menu.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SplitOptions {
+    pub timing: Timing,
+    pub unit: SplitUnit,
+    pub source: SourceMode,
+    /// Lay the glyphs out around the origin and move them with a group
+    /// control that keeps the source's position animation.
+    pub keep_motion: bool,
+    /// Gather the glyphs under a group control that leaves them in place,
+    /// keeping the timeline tidy. The host can't create scenes, so a group
+    /// is the only container available.
+    pub group: bool,
+    /// Put a copy of every glyph, `stroke_size_delta` larger and in the
+    /// stroke color, on the layer below it as a thick outline.
+    pub stroke: bool,
+    /// Join the text of all selected objects, in layer and start order, and
+    /// lay it out as one object with the first one's settings. Tags left
+    /// open in one object's text don't reach the next.
+    pub merge: bool,
+}
+
+impl Default for SplitOptions {
+    fn default() -> Self {
+        SplitOptions {
+            timing: Timing::Shared,
+            unit: SplitUnit::Glyph,
+            source: SourceMode::Delete,
+            keep_motion: false,
+            group: false,
+            stroke: false,
+            merge: false,
+        }
+    }
+}
+
+/// What happens to a source object once its glyphs have been created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceMode {
+    Delete,
+    /// Keep the source as an invisible reference for re-editing.
+    KeepHidden,
+}
+
+/// Source objects sorted by what to do with them after creation.
+#[derive(Debug)]
+pub struct SourcePlan<H> {
+    pub to_delete: Vec<H>,
+    pub to_hide: Vec<H>,
+    /// Warnings about sources whose glyphs may not match them, such as
+    /// ones with a time effect, logged as they were found.
+    pub warnings: Vec<String>,
+}
+
+impl<H> Default for SourcePlan<H> {
+    fn default() -> Self {
+        SourcePlan {
+            to_delete: Vec::new(),
+            to_hide: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl<H> SourcePlan<H> {
+    pub fn push(&mut self, handle: H, mode: SourceMode) {
+        match mode {
+            SourceMode::Delete => self.to_delete.push(handle),
+            SourceMode::KeepHidden => self.to_hide.push(handle),
+        }
+    }
+}
+
+fn layout_params(text: &TextParams, origin: (f32, f32), config: &SplitConfig) -> LayoutParams {
+    LayoutParams {
+        x: origin.0,
+        y: origin.1,
+        size: text.size,
+        kern: text.kern,
+        lnsp: text.lnsp,
+        min_size: config.min_size,
+        max_size: config.max_size,
+        line_height: config.line_height,
+    }
+}
+
+fn parse_text(text: &str, config: &SplitConfig) -> Result<Vec<TextElement>, SplitError> {
+    parse_texts(&[text], config)
+}
+
+/// Parses each text on its own, so nothing left open in one, not even a
+/// trailing `\`, reaches into the next, and joins them so that each
+/// continues the last line and page of the one before it.
+fn parse_texts(texts: &[&str], config: &SplitConfig) -> Result<Vec<TextElement>, SplitError> {
+    let mut elements = Vec::new();
+    let mut end = TextElement::default();
+    for text in texts {
+        let (parsed, text_end) =
+            parse_markup_with_end(text, &config.parse_options()).map_err(|message| {
+                SplitError::Parse {
+                    text: text.to_string(),
+                    message,
+                }
+            })?;
+        let continued = |mut el: TextElement| {
+            // Lines count from zero again after a `<page>`.
+            if el.page_index.unwrap_or(0) == 0 {
+                el.line_index = Some(el.line_index.unwrap_or(0) + end.line_index.unwrap_or(0));
+            }
+            el.page_index = Some(el.page_index.unwrap_or(0) + end.page_index.unwrap_or(0));
+            el
+        };
+        elements.extend(parsed.into_iter().map(continued));
+        end = continued(text_end);
+    }
+    #[cfg_attr(not(feature = "font-metrics"), allow(unused_mut))]
+    let mut elements = if config.trim_lines {
+        trim_lines(&elements)
+    } else {
+        elements
+    };
+    #[cfg(feature = "font-metrics")]
+    if config.replace_missing_fonts {
+        replace_missing_fonts(&mut elements, config);
+    }
+    Ok(elements)
+}
+
+/// Points every markup font list at its first font with a file in
+/// `font_files`, or at `fallback_font` (the object's font when unset) when
+/// none has one. Warns once for every replaced font and returns them.
+#[cfg(feature = "font-metrics")]
+fn replace_missing_fonts(elements: &mut [TextElement], config: &SplitConfig) -> Vec<String> {
+    let mut replaced: Vec<String> = Vec::new();
+    for el in elements {
+        let Some(font) = el.font.clone() else {
+            continue;
+        };
+        let mut fonts = std::iter::once(&font).chain(el.font_fallbacks.iter().flatten());
+        let available = fonts
+            .find(|font| crate::metrics::has_font_file(font, &config.font_files))
+            .cloned();
+        if available.as_ref() == Some(&font) {
+            continue;
+        }
+        let substitute = available.or_else(|| config.fallback_font.clone());
+        if !replaced.contains(&font) {
+            aviutl2::log::warn!(
+                "フォント {} が見つからないため {} を使用します",
+                font,
+                substitute.as_deref().unwrap_or("オブジェクトのフォント")
+            );
+            replaced.push(font);
+        }
+        el.font = substitute;
+        el.font_fallbacks = None;
+    }
+    replaced
+}
+
+/// Metrics for the object's font, stepping by `monospace_width` when set.
+/// The layout is scaled afterwards, so the width is unscaled first.
+fn glyph_metrics(font: &str, config: &SplitConfig) -> Box<dyn GlyphMetrics> {
+    let font = resolve_font(None, font, config.fallback_font.as_deref());
+    let metrics = metrics_for(font, &config.font_files);
+    match config.monospace_width {
+        Some(width) => Box::new(Monospace {
+            width: width / config.scale_factor,
+            inner: metrics,
+        }),
+        None => metrics,
+    }
+}
+
+/// Sizes the `<sauto>` runs to `fit_width`. The layout is scaled afterwards,
+/// so the width is unscaled first.
+fn fit_lines(
+    elements: Vec<TextElement>,
+    layout: &LayoutParams,
+    metrics: &dyn GlyphMetrics,
+    config: &SplitConfig,
+) -> Vec<TextElement> {
+    match config.fit_width {
+        Some(width) => fit_auto_sizes(&elements, layout, width / config.scale_factor, metrics),
+        None => elements,
+    }
+}
+
+/// Returns the `(width, height)` the glyphs of `text` would cover, without
+/// splitting. Pages are shown one at a time, so this is the largest page.
+pub fn measure_source(
+    text: &str,
+    params: &TextParams,
+    config: &SplitConfig,
+) -> Result<(f32, f32), SplitError> {
+    let (_, text) = take_alignment_directive(text);
+    let elements = parse_text(text, config)?;
+    let layout = layout_params(params, (0.0, 0.0), config);
+    let metrics = glyph_metrics(&params.font, config);
+    let elements = fit_lines(elements, &layout, metrics.as_ref(), config);
+    let (width, height) = elements
+        .chunk_by(|a, b| a.page_index == b.page_index)
+        .map(|segment| measure_text_with(segment, &layout, metrics.as_ref()))
+        .fold((0.0, 0.0), |(w, h), (sw, sh)| {
+            (f32::max(w, sw), f32::max(h, sh))
+        });
+    Ok((width * config.scale_factor, height * config.scale_factor))
+}
+
+/// Number of objects splitting `text` creates, counting stroke copies and
+/// group controls and leaving out whatever `config` skips, as
+/// `compute_creation_infos` plans them.
+pub fn count_glyphs(
+    text: &str,
+    params: &SplitParams,
+    alignment: &TextAlignment,
+    options: &SplitOptions,
+    config: &SplitConfig,
+) -> Result<usize, SplitError> {
+    Ok(compute_creation_infos(text, params, alignment, options, config)?.len())
+}
+
+/// One glyph object as passed to the hook of `compute_creation_infos_with`.
+#[derive(Debug, Clone, Copy)]
+pub struct GlyphContext<'a> {
+    /// What the object shows: a glyph, or a word or chunk with other units.
+    pub text: &'a str,
+    /// Position among the objects of its `<page>` segment.
+    pub index: usize,
+    /// Top-left corner in canvas pixels, after scaling and `canvas_offset`.
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    /// Markup color, with any palette or `<colorfn>` applied.
+    pub color: Option<&'a str>,
+    /// The run the glyph comes from, for the rest of its style.
+    pub element: &'a TextElement,
+    pub frame: usize,
+    pub length: usize,
+    /// Whether this is the copy drawn below the glyph by the stroke split.
+    pub is_stroke: bool,
+}
+
+impl<'a> GlyphContext<'a> {
+    fn new(glyph: &'a PlacedGlyph, index: usize, frame: usize, length: usize) -> Self {
+        GlyphContext {
+            text: &glyph.text,
+            index,
+            x: glyph.x,
+            y: glyph.y,
+            size: glyph.size,
+            color: glyph.color.as_deref(),
+            element: glyph.element,
+            frame,
+            length,
+            is_stroke: false,
+        }
+    }
+}
+
+/// Computes the objects that splitting `text` produces, without touching the
+/// host. Glyphs are planned on the layers directly above `params.layer`.
+///
+/// A leading `<align:h,v>` directive overrides `alignment`.
+///
+/// Glyphs under `<layer:N>` go on the layers from the `N`th above the source
+/// instead, one each, as glyphs shown at the same time can't share a layer.
+/// The other glyphs fill the layers above the source around them.
+///
+/// With `stroke`, each glyph takes two adjacent layers, its stroke copy
+/// below it.
+///
+/// Each `<page>` segment is laid out on its own and shown in its share of the
+/// source's range, so the segments play one after another on the same layers.
+pub fn compute_creation_infos(
+    text: &str,
+    params: &SplitParams,
+    alignment: &TextAlignment,
+    options: &SplitOptions,
+    config: &SplitConfig,
+) -> Result<Vec<CreationInfo>, SplitError> {
+    compute_creation_infos_with(text, params, alignment, options, config, |_, alias| alias)
+}
+
+/// Like `compute_creation_infos`, passing every glyph object's alias
+/// through `hook` along with the glyph it renders. Whatever `hook` returns
+/// is created instead, for changes the templates can't express. The group
+/// control of `keep_motion` and `group` isn't passed.
+pub fn compute_creation_infos_with(
+    text: &str,
+    params: &SplitParams,
+    alignment: &TextAlignment,
+    options: &SplitOptions,
+    config: &SplitConfig,
+    hook: impl Fn(&GlyphContext, String) -> String,
+) -> Result<Vec<CreationInfo>, SplitError> {
+    let (directive, text) = take_alignment_directive(text);
+    let alignment = directive.as_ref().unwrap_or(alignment);
+    let elements = parse_text(text, config)?;
+    plan_elements(elements, params, alignment, options, config, hook)
+}
+
+/// Like `compute_creation_infos`, for texts split as one. Each continues
+/// the last line of the one before it, and only the first text's
+/// `<align:h,v>` directive counts; tags left open in one text, such as
+/// `<#f00>` or `<layer:2>`, end with it.
+pub fn compute_merged_creation_infos(
+    texts: &[&str],
+    params: &SplitParams,
+    alignment: &TextAlignment,
+    options: &SplitOptions,
+    config: &SplitConfig,
+) -> Result<Vec<CreationInfo>, SplitError> {
+    let Some((first, rest)) = texts.split_first() else {
+        return Ok(Vec::new());
+    };
+    let (directive, first) = take_alignment_directive(first);
+    let alignment = directive.as_ref().unwrap_or(alignment);
+    // Later directives would be text in a single object, but they only
+    // lead their own object's text here.
+    let texts: Vec<&str> = std::iter::once(first)
+        .chain(rest.iter().map(|text| take_alignment_directive(text).1))
+        .collect();
+    let elements = parse_texts(&texts, config)?;
+    plan_elements(elements, params, alignment, options, config, |_, alias| {
+        alias
+    })
+}
+
+fn plan_elements(
+    elements: Vec<TextElement>,
+    params: &SplitParams,
+    alignment: &TextAlignment,
+    options: &SplitOptions,
+    config: &SplitConfig,
+    hook: impl Fn(&GlyphContext, String) -> String,
+) -> Result<Vec<CreationInfo>, SplitError> {
+    // With a group control, glyphs sit one layer higher. When it carries
+    // the motion, they are positioned relative to it.
+    let grouped = options.keep_motion || options.group;
+    let origin = if options.keep_motion {
+        (0.0, 0.0)
+    } else {
+        (params.draw.x, params.draw.y)
+    };
+    let first_layer = params.layer + if grouped { 2 } else { 1 };
+    let stride = if options.stroke { 2 } else { 1 };
+    let layout = layout_params(&params.text, origin, config);
+    let factor = config.scale_factor;
+    let text_params = TextParams {
+        kern: params.text.kern * factor,
+        ..params.text.clone()
+    };
+    let ctx = AliasContext {
+        text: &text_params,
+        draw: &params.draw,
+        fallback_font: config.fallback_font.as_deref(),
+        default_outline_width: config.outline_width,
+        origin: config.glyph_origin,
+    };
+
+    let metrics = glyph_metrics(&params.text.font, config);
+    let elements = fit_lines(elements, &layout, metrics.as_ref(), config);
+
+    let pages = elements.last().and_then(|el| el.page_index).unwrap_or(0) + 1;
+    let mut creation_infos = Vec::new();
+    // Whether each object's layer came from `<layer:N>`, so that packing
+    // leaves it alone, and the layers those objects take.
+    let mut hinted = Vec::new();
+    let mut claimed = HashSet::new();
+    let mut strokes = Vec::new();
+    for segment in elements.chunk_by(|a, b| a.page_index == b.page_index) {
+        let page = segment[0].page_index.unwrap_or(0);
+        let (start, end) = page_range(page, pages, params.start, params.end);
+
+        let mut glyphs = place_glyphs_with(segment, &layout, alignment, metrics.as_ref());
+        if factor != 1.0 {
+            scale_glyphs(&mut glyphs, factor);
+        }
+        // Applied after scaling, as it is already in canvas pixels.
+        let (dx, dy) = config.canvas_offset_px();
+        for glyph in &mut glyphs {
+            glyph.x += dx;
+            glyph.y += dy;
+        }
+        glyphs = match options.unit {
+            SplitUnit::Glyph => glyphs,
+            SplitUnit::Word => group_words(glyphs),
+            SplitUnit::Chunks(count) => group_chunks(glyphs, count),
+            SplitUnit::FixedChunks(size) => group_fixed_chunks(glyphs, size),
+        };
+        if config.reverse_order {
+            reverse_lines(&mut glyphs);
+        }
+        // Skipped glyphs are dropped only after layout, so they still take up
+        // their width and whitespace-only lines keep their place.
+        let glyphs = glyphs
+            .into_iter()
+            .filter(|glyph| !config.skips_glyph(&glyph.text));
+        // Glyphs placed so far from each `<layer:N>` base on this page.
+        let mut hint_counts: HashMap<usize, usize> = HashMap::new();
+        let mut auto_index = 0;
+        for (index, glyph) in glyphs.enumerate() {
+            let onset = options.timing.glyph_start(index, start)
+                + glyph.frame_offset(config.timing_default).unwrap_or(0);
+            // An onset at or past the end still shows the glyph for its
+            // last frame rather than making an empty object.
+            let (frame, length) = clamp_onset(onset, start, end);
+            if frame < onset {
+                aviutl2::log::warn!(
+                    "{} の表示開始が範囲の終わりを超えるため、最後のフレームに合わせました",
+                    glyph.text
+                );
+            }
+            let alias = glyph_alias(&glyph, frame, end, &ctx, metrics.as_ref(), config);
+            let alias = hook(&GlyphContext::new(&glyph, index, frame, length), alias);
+            let alias = check_alias_length(alias, &glyph.text)?;
+            let stroke = if options.stroke {
+                let stroke = stroke_glyph(&glyph, &params.text.subcolor, config);
+                let alias = glyph_alias(&stroke, frame, end, &ctx, metrics.as_ref(), config);
+                let glyph = GlyphContext {
+                    is_stroke: true,
+                    ..GlyphContext::new(&stroke, index, frame, length)
+                };
+                Some(check_alias_length(hook(&glyph, alias), &stroke.text)?)
+            } else {
+                None
+            };
+            let layer = match glyph.element.layer {
+                Some(offset) => {
+                    let count = hint_counts.entry(offset).or_default();
+                    let layer = params.layer + offset + *count * stride;
+                    *count += 1;
+                    if layer + stride - 1 > MAX_LAYER {
+                        return Err(SplitError::LayerOverflow {
+                            layer: layer + stride - 1,
+                        });
+                    }
+                    claimed.extend(layer..layer + stride);
+                    layer
+                }
+                // A slot among the unhinted glyphs, given its layer below.
+                None => {
+                    auto_index += 1;
+                    auto_index - 1
+                }
+            };
+            creation_infos.push((alias, layer, frame, length));
+            hinted.push(glyph.element.layer.is_some());
+            strokes.push(stroke);
+        }
+    }
+    let mut unhinted: Vec<_> = creation_infos
+        .iter_mut()
+        .zip(&hinted)
+        .filter(|(_, hinted)| !**hinted)
+        .map(|(info, _)| info)
+        .collect();
+    if config.layer_policy == LayerPolicy::Compact {
+        let ranges: Vec<_> = unhinted
+            .iter()
+            .map(|&&mut (_, _, frame, length)| (frame, length))
+            .collect();
+        for (info, row) in unhinted.iter_mut().zip(compact_layers(&ranges, 0)) {
+            info.1 = row;
+        }
+    }
+    let slots = unhinted.iter().map(|info| info.1 + 1).max().unwrap_or(0);
+    let layers = free_layers(first_layer, stride, &claimed, slots);
+    for info in unhinted {
+        info.1 = layers[info.1];
+    }
+    let mut creation_infos: Vec<_> = creation_infos
+        .into_iter()
+        .zip(strokes)
+        .flat_map(|((alias, layer, frame, length), stroke)| match stroke {
+            Some(stroke) => vec![
+                (stroke, layer, frame, length),
+                (alias, layer + 1, frame, length),
+            ],
+            None => vec![(alias, layer, frame, length)],
+        })
+        .collect();
+
+    if grouped {
+        let layers = creation_infos
+            .iter()
+            .map(|(_, layer, ..)| (layer + 1).saturating_sub(first_layer))
+            .max()
+            .unwrap_or(0);
+        let (x, y) = match &params.draw.position_tracks {
+            _ if !options.keep_motion => ("0.00".to_string(), "0.00".to_string()),
+            Some(tracks) => tracks.clone(),
+            None => (
+                format!("{:.2}", params.draw.x),
+                format!("{:.2}", params.draw.y),
+            ),
+        };
+        let alias = render_group_alias(&x, &y, params.start, params.end, layers);
+        let length = params.end.saturating_sub(params.start);
+        creation_infos.insert(0, (alias, params.layer + 1, params.start, length));
+    }
+    Ok(creation_infos)
+}
+
+/// Passes `alias` through unless it exceeds `MAX_ALIAS_BYTES`.
+fn check_alias_length(alias: String, text: &str) -> Result<String, SplitError> {
+    if alias.len() <= MAX_ALIAS_BYTES {
+        return Ok(alias);
+    }
+    Err(SplitError::AliasTooLong {
+        text: text.chars().take(16).collect(),
+        length: alias.len(),
+        limit: MAX_ALIAS_BYTES,
+    })
+}
+
+/// Joins the objects planned per source object, moving each block so that
+/// the blocks follow one another from `first_layer` up without sharing a
+/// layer. Layers within a block keep their distances.
+pub fn stack_blocks(blocks: Vec<Vec<CreationInfo>>, first_layer: usize) -> Vec<CreationInfo> {
+    let mut next = first_layer;
+    let mut stacked = Vec::new();
+    for mut block in blocks {
+        let (Some(low), Some(high)) = (
+            block.iter().map(|info| info.1).min(),
+            block.iter().map(|info| info.1).max(),
+        ) else {
+            continue;
+        };
+        for info in &mut block {
+            info.1 = info.1 - low + next;
+        }
+        next += high - low + 1;
+        stacked.extend(block);
+    }
+    stacked
+}
+
+/// The copy of `glyph` drawn below it by the stroke split, on the same
+/// position.
+fn stroke_glyph<'a>(
+    glyph: &PlacedGlyph<'a>,
+    subcolor: &str,
+    config: &SplitConfig,
+) -> PlacedGlyph<'a> {
+    let color = config.stroke_color.as_deref().unwrap_or(subcolor);
+    PlacedGlyph {
+        size: glyph.size + config.stroke_size_delta,
+        color: Some(color.to_string().into()),
+        ..glyph.clone()
+    }
+}
+
+/// Renders `glyph` as a path figure when `glyph_paths` is on and the font
+/// has contours for it, as a text object otherwise.
+#[cfg_attr(not(feature = "glyph-paths"), allow(unused_variables))]
+fn glyph_alias(
+    glyph: &PlacedGlyph,
+    start: usize,
+    end: usize,
+    ctx: &AliasContext,
+    metrics: &dyn GlyphMetrics,
+    config: &SplitConfig,
+) -> String {
+    #[cfg(feature = "glyph-paths")]
+    if config.glyph_paths
+        && let Some(path) = crate::entry::glyph_path_data(glyph, ctx.text.kern, ctx.origin, metrics)
+    {
+        return crate::entry::render_path_alias(glyph, &path, start, end, ctx);
+    }
+    render_glyph_alias(glyph, start, end, ctx)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::CharClass, parser::alignment::parse_alignment};
+
+    fn params() -> SplitParams {
+        SplitParams {
+            text: TextParams {
+                size: 30.0,
+                kern: 2.0,
+                lnsp: 0.0,
+                font: "MS Gothic".to_string(),
+                color: "ffffff".to_string(),
+                subcolor: "000000".to_string(),
+                decoration: "標準文字".to_string(),
+                bold: "0".to_string(),
+                italic: "0".to_string(),
+            },
+            draw: DrawParams::default(),
+            layer: 3,
+            start: 10,
+            end: 70,
+        }
+    }
+
+    fn compute_with(
+        text: &str,
+        params: &SplitParams,
+        options: &SplitOptions,
+        config: &SplitConfig,
+    ) -> Vec<CreationInfo> {
+        compute_creation_infos(
+            text,
+            params,
+            &parse_alignment("左寄せ[上]"),
+            options,
+            config,
+        )
+        .unwrap()
+    }
+
+    fn compute(text: &str, config: &SplitConfig) -> Vec<CreationInfo> {
+        compute_with(text, &params(), &SplitOptions::default(), config)
+    }
+
+    #[test]
+    fn test_compute_creation_infos() {
+        let infos = compute("a<#ff0000>b", &SplitConfig::default());
+        assert_eq!(infos.len(), 2);
+
+        let (alias, layer, frame, length) = &infos[0];
+        assert_eq!((*layer, *frame, *length), (4, 10, 60));
+        assert!(alias.contains("frame=10,70\n"));
+        assert!(alias.contains("テキスト=a\n"));
+        assert!(alias.contains("文字色=ffffff\n"));
+        assert!(alias.contains("X=0.00\n"));
+
+        let (alias, layer, ..) = &infos[1];
+        assert_eq!(*layer, 5);
+        assert!(alias.contains("テキスト=b\n"));
+        assert!(alias.contains("文字色=ff0000\n"));
+        assert!(alias.contains("X=32.00\n"));
+    }
+
+    #[test]
+    fn test_merged_texts_keep_trailing_backslash() {
+        let infos = compute_merged_creation_infos(
+            &["a\\", "<#ff0000>b\\n", "<page>", "c"],
+            &params(),
+            &parse_alignment("左寄せ[上]"),
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+        let texts: Vec<_> = infos
+            .iter()
+            .map(|(alias, ..)| alias.lines().find(|line| line.starts_with("テキスト=")))
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                Some("テキスト=a"),
+                Some("テキスト=\\"),
+                Some("テキスト=b"),
+                Some("テキスト=c"),
+            ]
+        );
+        assert!(infos[1].0.contains("文字色=ffffff\n"));
+        assert!(infos[2].0.contains("文字色=ff0000\n"));
+        // The break and `<page>` left at the ends of texts still count.
+        assert_eq!(infos[3].2, 40);
+    }
+
+    #[test]
+    fn test_keep_hidden_does_not_delete_source() {
+        let mut plan = SourcePlan::default();
+        plan.push(1, SourceMode::KeepHidden);
+        plan.push(2, SourceMode::Delete);
+        assert_eq!(plan.to_delete, vec![2]);
+        assert_eq!(plan.to_hide, vec![1]);
+    }
+
+    #[test]
+    fn test_scale_factor_doubles_sizes_and_positions() {
+        let config = SplitConfig {
+            scale_factor: 2.0,
+            ..SplitConfig::default()
+        };
+        let infos = compute("ab", &config);
+        assert!(infos[0].0.contains("サイズ=60.00\n"));
+        assert!(infos[0].0.contains("字間=4.00\n"));
+        assert!(infos[1].0.contains("X=64.00\n"));
+        let base = compute("ab", &SplitConfig::default());
+        assert!(base[0].0.contains("サイズ=30.00\n"));
+        assert!(base[1].0.contains("X=32.00\n"));
+    }
+
+    #[test]
+    fn test_pages_create_time_offset_groups() {
+        let infos = compute("ab<page>c", &SplitConfig::default());
+        let ranges: Vec<_> = infos.iter().map(|(_, l, f, n)| (*l, *f, *n)).collect();
+        assert_eq!(ranges, [(4, 10, 30), (5, 10, 30), (4, 40, 30)]);
+        assert!(infos[2].0.contains("frame=40,70\n"));
+        assert!(infos[2].0.contains("X=0.00\n"));
+    }
+
+    #[test]
+    fn test_timing_list_offsets_frames() {
+        let infos = compute("<timing:0,3>abc", &SplitConfig::default());
+        let frames: Vec<_> = infos.iter().map(|(_, _, f, n)| (*f, *n)).collect();
+        assert_eq!(frames, [(10, 60), (13, 57), (13, 57)]);
+        assert!(infos[1].0.contains("frame=13,70\n"));
+
+        let infos = compute("<timing:0,60,200>abc", &SplitConfig::default());
+        let frames: Vec<_> = infos.iter().map(|(_, _, f, n)| (*f, *n)).collect();
+        assert_eq!(frames, [(10, 60), (69, 1), (69, 1)]);
+        assert!(infos[2].0.contains("frame=69,70\n"));
+    }
+
+    #[test]
+    fn test_typewriter_past_end_keeps_last_frame() {
+        let options = SplitOptions {
+            timing: Timing::Typewriter { step: 25 },
+            ..SplitOptions::default()
+        };
+        let infos = compute_with("abcd", &params(), &options, &SplitConfig::default());
+        let frames: Vec<_> = infos.iter().map(|(_, _, f, n)| (*f, *n)).collect();
+        assert_eq!(frames, [(10, 60), (35, 35), (60, 10), (69, 1)]);
+        assert!(infos.iter().all(|(_, _, _, length)| *length > 0));
+        assert!(infos[3].0.contains("frame=69,70\n"));
+    }
+
+    #[test]
+    fn test_karaoke_word_onsets() {
+        let options = SplitOptions {
+            unit: SplitUnit::Word,
+            ..SplitOptions::default()
+        };
+        let infos = compute_with(
+            "<timing:0,5,12>I love you",
+            &params(),
+            &options,
+            &SplitConfig::default(),
+        );
+        let onsets: Vec<_> = infos.iter().map(|(_, l, f, n)| (*l, *f, *n)).collect();
+        assert_eq!(onsets, [(4, 10, 60), (5, 15, 55), (6, 22, 48)]);
+        assert!(infos[1].0.contains("テキスト=love\n"));
+        assert!(infos[2].0.contains("frame=22,70\n"));
+    }
+
+    #[test]
+    fn test_keep_motion_adds_group_control() {
+        let mut params = params();
+        params.draw.x = 10.0;
+        params.draw.position_tracks =
+            Some(("10.00,50.00,直線移動".to_string(), "0.00".to_string()));
+        let options = SplitOptions {
+            keep_motion: true,
+            ..SplitOptions::default()
+        };
+        let infos = compute_with("ab", &params, &options, &SplitConfig::default());
+        let layers: Vec<_> = infos.iter().map(|(_, l, ..)| *l).collect();
+        assert_eq!(layers, [4, 5, 6]);
+        assert!(infos[0].0.contains("effect.name=グループ制御\n"));
+        assert!(infos[0].0.contains("X=10.00,50.00,直線移動\n"));
+        assert!(infos[0].0.contains("対象レイヤー数=2\n"));
+        assert!(infos[1].0.contains("X=0.00\n"));
+        assert!(infos[2].0.contains("X=32.00\n"));
+    }
+
+    #[test]
+    fn test_stroke_duplicates_every_glyph() {
+        let options = SplitOptions {
+            stroke: true,
+            ..SplitOptions::default()
+        };
+        let config = SplitConfig {
+            stroke_size_delta: 4.0,
+            ..SplitConfig::default()
+        };
+        let infos = compute_with("ab", &params(), &options, &config);
+        let layers: Vec<_> = infos.iter().map(|(_, layer, ..)| *layer).collect();
+        assert_eq!(layers, [4, 5, 6, 7]);
+
+        let (stroke, fill) = (&infos[2].0, &infos[3].0);
+        assert!(stroke.contains("テキスト=b\n") && fill.contains("テキスト=b\n"));
+        assert!(stroke.contains("サイズ=34.00\n"));
+        assert!(stroke.contains("文字色=000000\n"));
+        assert!(fill.contains("サイズ=30.00\n"));
+        assert!(fill.contains("文字色=ffffff\n"));
+        assert!(stroke.contains("X=32.00\n") && fill.contains("X=32.00\n"));
+
+        let config = SplitConfig {
+            stroke_color: Some("ff0000".to_string()),
+            ..config
+        };
+        let infos = compute_with("a", &params(), &options, &config);
+        assert!(infos[0].0.contains("文字色=ff0000\n"));
+    }
+
+    #[test]
+    fn test_hook_rewrites_every_glyph_alias() {
+        let options = SplitOptions {
+            keep_motion: true,
+            ..SplitOptions::default()
+        };
+        let infos = compute_creation_infos_with(
+            "ab",
+            &params(),
+            &parse_alignment("左寄せ[上]"),
+            &options,
+            &SplitConfig::default(),
+            |glyph, alias| format!("{}-- {}:{}:{}\n", alias, glyph.index, glyph.text, glyph.x),
+        )
+        .unwrap();
+        assert_eq!(infos.len(), 3);
+        assert!(!infos[0].0.contains("--"));
+        assert!(infos[1].0.ends_with("-- 0:a:0\n"));
+        assert!(infos[2].0.ends_with("-- 1:b:32\n"));
+    }
+
+    #[test]
+    fn test_oversized_alias_is_rejected() {
+        let mut params = params();
+        params.text.font = "F".repeat(MAX_ALIAS_BYTES);
+        let error = compute_creation_infos(
+            "ab",
+            &params,
+            &parse_alignment("左寄せ[上]"),
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            SplitError::AliasTooLong { ref text, length, limit: MAX_ALIAS_BYTES }
+                if text == "a" && length > MAX_ALIAS_BYTES
+        ));
+    }
+
+    #[test]
+    fn test_stack_blocks_above_highest_source() {
+        let lower = compute("ab", &SplitConfig::default());
+        let upper = compute_with(
+            "cd",
+            &SplitParams {
+                layer: 5,
+                ..params()
+            },
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        );
+        assert_eq!(lower.iter().map(|info| info.1).collect::<Vec<_>>(), [4, 5]);
+
+        let stacked = stack_blocks(vec![lower, upper], 6);
+        let layers: Vec<_> = stacked.iter().map(|(_, layer, ..)| *layer).collect();
+        assert_eq!(layers, [6, 7, 8, 9]);
+        assert!(stacked[2].0.contains("テキスト=c\n"));
+    }
+
+    #[cfg(feature = "font-metrics")]
+    #[test]
+    fn test_missing_fonts_are_replaced() {
+        let path = std::env::temp_dir().join("textsplit_missing_font_test.ttf");
+        std::fs::write(&path, b"").unwrap();
+        let config = SplitConfig {
+            font_files: [("Exists".to_string(), path.display().to_string())].into(),
+            fallback_font: Some("Fallback".to_string()),
+            replace_missing_fonts: true,
+            ..SplitConfig::default()
+        };
+        let mut elements = crate::parser::markup::parse_markup(
+            "<s,Missing,>a<s,\"Gone\"|Exists,>b<s,Exists,>c<s,Missing,>d",
+        )
+        .unwrap();
+        let replaced = replace_missing_fonts(&mut elements, &config);
+        assert_eq!(replaced, ["Missing", "Gone"]);
+        let fonts: Vec<_> = elements.iter().map(|el| el.font.as_deref()).collect();
+        assert_eq!(
+            fonts,
+            [
+                Some("Fallback"),
+                Some("Exists"),
+                Some("Exists"),
+                Some("Fallback")
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_whitespace_only_line_keeps_centering() {
+        let config = SplitConfig {
+            skip_spaces: true,
+            ..SplitConfig::default()
+        };
+        let infos = compute_creation_infos(
+            "ab\\n   \\ncd",
+            &params(),
+            &parse_alignment("中央揃え[中]"),
+            &SplitOptions::default(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(infos.len(), 4);
+        let position = |alias: &str| {
+            let value = |key: &str| -> f32 {
+                let line = alias.lines().find(|l| l.starts_with(key)).unwrap();
+                line[key.len()..].parse().unwrap()
+            };
+            (value("X="), value("Y="))
+        };
+        let (ax, ay) = position(&infos[0].0);
+        let (cx, cy) = position(&infos[2].0);
+        assert_eq!(ax, -31.0);
+        assert_eq!(cx, ax);
+        assert_eq!(position(&infos[3].0).0, 1.0);
+        assert_eq!(cy - ay, 60.0);
+    }
+
+    #[test]
+    fn test_measure_source_multi_line_mixed_size() {
+        let config = SplitConfig::default();
+        let text = &params().text;
+        assert_eq!(
+            measure_source("ab\\n<s20>c", text, &config),
+            Ok((62.0, 50.0))
+        );
+
+        let config = SplitConfig {
+            scale_factor: 2.0,
+            ..SplitConfig::default()
+        };
+        assert_eq!(
+            measure_source("ab<page><s60>c", text, &config),
+            Ok((124.0, 120.0))
+        );
+    }
+
+    #[test]
+    fn test_auto_size_fits_width() {
+        let text = &params().text;
+        let config = SplitConfig {
+            fit_width: Some(200.0),
+            ..SplitConfig::default()
+        };
+        assert_eq!(
+            measure_source("<sauto>ab", text, &config),
+            Ok((200.0, 99.0))
+        );
+
+        let config = SplitConfig {
+            scale_factor: 2.0,
+            ..config
+        };
+        assert_eq!(
+            measure_source("<sauto>ab", text, &config),
+            Ok((200.0, 98.0))
+        );
+    }
+
+    #[test]
+    fn test_monospace_width_ignores_glyph_size() {
+        let config = SplitConfig {
+            monospace_width: Some(25.0),
+            ..SplitConfig::default()
+        };
+        let infos = compute("a<s10>b<s60>c", &config);
+        for (info, x) in infos.iter().zip(["0.00", "27.00", "54.00"]) {
+            assert!(info.0.contains(&format!("X={}\n", x)));
+        }
+        assert!(infos[2].0.contains("サイズ=60.00\n"));
+    }
+
+    #[test]
+    fn test_layer_hint_overrides_planned_layer() {
+        let infos = compute("a<layer:5>bc<layer>d", &SplitConfig::default());
+        let layers: Vec<_> = infos.iter().map(|(_, l, ..)| *l).collect();
+        assert_eq!(layers, [4, 8, 9, 5]);
+
+        let error = compute_creation_infos(
+            "<layer:1000>a",
+            &params(),
+            &parse_alignment("左寄せ[上]"),
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        );
+        assert_eq!(error, Err(SplitError::LayerOverflow { layer: 1003 }));
+    }
+
+    #[test]
+    fn test_canvas_offset_scales_with_canvas_size() {
+        let config = SplitConfig {
+            canvas_offset: (10.0, -50.0),
+            ..SplitConfig::default()
+        };
+        let infos = compute("a", &config);
+        assert!(infos[0].0.contains("X=192.00\nY=-540.00\n"));
+
+        let config = SplitConfig {
+            canvas_size: (1280, 720),
+            ..config
+        };
+        let infos = compute("a", &config);
+        assert!(infos[0].0.contains("X=128.00\nY=-360.00\n"));
+    }
+
+    #[test]
+    fn test_placeholders_in_text_stay_literal() {
+        let infos = compute("<nosplit>{ox}</nosplit>", &SplitConfig::default());
+        assert_eq!(infos.len(), 1);
+        assert!(infos[0].0.contains("テキスト={ox}\n"));
+        assert!(infos[0].0.contains("X=0.00\n"));
+    }
+
+    #[test]
+    fn test_count_glyphs_per_unit() {
+        let count = |text: &str, options: &SplitOptions, config: &SplitConfig| {
+            let alignment = parse_alignment("左寄せ[上]");
+            count_glyphs(text, &params(), &alignment, options, config).unwrap()
+        };
+        let glyph = SplitOptions::default();
+        let word = SplitOptions {
+            unit: SplitUnit::Word,
+            ..SplitOptions::default()
+        };
+        let config = SplitConfig::default();
+        assert_eq!(count("ab cd\\nef", &glyph, &config), 7);
+        assert_eq!(count("ab cd\\nef", &word, &config), 3);
+        assert_eq!(count("ab<split>cd</split>", &glyph, &config), 3);
+
+        let skipping = SplitConfig {
+            skip_spaces: true,
+            ..SplitConfig::default()
+        };
+        assert_eq!(count("ab cd\\nef", &glyph, &skipping), 6);
+
+        let stroke_group = SplitOptions {
+            stroke: true,
+            group: true,
+            ..SplitOptions::default()
+        };
+        assert_eq!(count("abc", &stroke_group, &config), 7);
+        assert_eq!(
+            count("abc", &stroke_group, &config),
+            compute_with("abc", &params(), &stroke_group, &config).len()
+        );
+    }
+
+    #[test]
+    fn test_chunks_split_line_evenly() {
+        let options = SplitOptions {
+            unit: SplitUnit::Chunks(5),
+            ..SplitOptions::default()
+        };
+        let infos = compute_with("abcdefghij", &params(), &options, &SplitConfig::default());
+        assert_eq!(infos.len(), 5);
+        assert!(infos[0].0.contains("テキスト=ab\n"));
+        assert!(infos[4].0.contains("テキスト=ij\n"));
+        assert!(infos[1].0.contains("X=64.00\n"));
+    }
+
+    #[test]
+    fn test_fixed_chunks_pair_glyphs() {
+        let options = SplitOptions {
+            unit: SplitUnit::FixedChunks(2),
+            ..SplitOptions::default()
+        };
+        let infos = compute_with("abcde", &params(), &options, &SplitConfig::default());
+        let texts: Vec<_> = infos
+            .iter()
+            .map(|(alias, ..)| alias.lines().find(|line| line.starts_with("テキスト=")))
+            .collect();
+        assert_eq!(
+            texts,
+            [Some("テキスト=ab"), Some("テキスト=cd"), Some("テキスト=e")]
+        );
+        assert!(infos[2].0.contains("X=128.00\n"));
+    }
+
+    #[test]
+    fn test_object_font_list_is_kept() {
+        let mut params = params();
+        params.text.font = "Meiryo,Arial".to_string();
+        let infos = compute_with(
+            "a<s,Consolas,>b",
+            &params,
+            &SplitOptions::default(),
+            &SplitConfig::default(),
+        );
+        assert!(infos[0].0.contains("フォント=Meiryo,Arial\n"));
+        assert!(infos[1].0.contains("フォント=Consolas\n"));
+    }
+
+    #[test]
+    fn test_alignment_directive_overrides_object() {
+        let infos = compute("<align:right,top>ab", &SplitConfig::default());
+        assert_eq!(infos.len(), 2);
+        assert!(infos[0].0.contains("テキスト=a\n"));
+        assert!(infos[0].0.contains("X=-62.00\n"));
+        assert!(infos[1].0.contains("X=-30.00\n"));
+    }
+
+    #[test]
+    fn test_skip_spaces_creates_only_letters() {
+        let config = SplitConfig {
+            skip_spaces: true,
+            ..SplitConfig::default()
+        };
+        let infos = compute("a b", &config);
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[1].1, 5);
+        assert!(infos[1].0.contains("X=64.00\n"));
+        assert_eq!(compute("a b", &SplitConfig::default()).len(), 3);
+    }
+
+    #[test]
+    fn test_skipped_punctuation_keeps_positions() {
+        let config = SplitConfig {
+            skip_classes: vec![CharClass::Punctuation],
+            ..SplitConfig::default()
+        };
+        let infos = compute("a,b、c!", &config);
+        assert_eq!(infos.len(), 3);
+        for (info, (text, x)) in infos.iter().zip([("a", "0"), ("b", "64"), ("c", "128")]) {
+            assert!(info.0.contains(&format!("テキスト={}\n", text)));
+            assert!(info.0.contains(&format!("X={}.00\n", x)));
+        }
+
+        let config = SplitConfig {
+            skip_chars: "b!".to_string(),
+            ..SplitConfig::default()
+        };
+        assert_eq!(compute("a,b、c!", &config).len(), 4);
+    }
+}
@@ -0,0 +1,623 @@
+use crate::error::SplitError;
+use aviutl2::generic::{EditSection, ObjectHandle};
+
+/// The host operations the menus need, so the whole split flow can also run
+/// against a mock timeline.
+pub trait Timeline {
+    type Handle: Clone;
+
+    fn selected_objects(&self) -> Result<Vec<Self::Handle>, SplitError>;
+
+    /// `(layer, start, end)` of the object.
+    fn layer_frame(&self, object: &Self::Handle) -> Result<(usize, usize, usize), SplitError>;
+
+    /// The item of the effect at `index`; `None` when there is no such
+    /// effect or item.
+    fn effect_item(
+        &self,
+        object: &Self::Handle,
+        effect: &str,
+        index: usize,
+        item: &str,
+    ) -> Option<String>;
+
+    fn alias(&self, object: &Self::Handle) -> Option<String>;
+
+    fn has_effect(&self, object: &Self::Handle, effect: &str) -> bool;
+
+    fn name(&self, object: &Self::Handle) -> Option<String>;
+
+    /// Creates an object from `alias`. The error is only reported, as
+    /// placement retries on the next layer.
+    fn create_object(
+        &mut self,
+        alias: &str,
+        layer: usize,
+        frame: usize,
+        length: usize,
+    ) -> Result<Self::Handle, String>;
+
+    fn delete_object(&mut self, object: &Self::Handle) -> Result<(), SplitError>;
+
+    fn set_effect_item(
+        &mut self,
+        object: &Self::Handle,
+        effect: &str,
+        index: usize,
+        item: &str,
+        value: &str,
+    ) -> Result<(), SplitError>;
+
+    fn move_object(
+        &mut self,
+        object: &Self::Handle,
+        layer: usize,
+        frame: usize,
+    ) -> Result<(), SplitError>;
+}
+
+impl Timeline for EditSection {
+    type Handle = ObjectHandle;
+
+    fn selected_objects(&self) -> Result<Vec<ObjectHandle>, SplitError> {
+        self.get_selected_objects().map_err(SplitError::host)
+    }
+
+    fn layer_frame(&self, object: &ObjectHandle) -> Result<(usize, usize, usize), SplitError> {
+        let frame = self
+            .object(object)
+            .get_layer_frame()
+            .map_err(SplitError::host)?;
+        Ok((frame.layer, frame.start, frame.end))
+    }
+
+    fn effect_item(
+        &self,
+        object: &ObjectHandle,
+        effect: &str,
+        index: usize,
+        item: &str,
+    ) -> Option<String> {
+        self.object(object)
+            .get_effect_item(effect, index, item)
+            .ok()
+    }
+
+    fn alias(&self, object: &ObjectHandle) -> Option<String> {
+        self.object(object).get_alias().ok()
+    }
+
+    fn has_effect(&self, object: &ObjectHandle, effect: &str) -> bool {
+        self.object(object)
+            .count_effect(effect)
+            .is_ok_and(|count| count > 0)
+    }
+
+    fn name(&self, object: &ObjectHandle) -> Option<String> {
+        self.object(object).get_name().ok()
+    }
+
+    fn create_object(
+        &mut self,
+        alias: &str,
+        layer: usize,
+        frame: usize,
+        length: usize,
+    ) -> Result<ObjectHandle, String> {
+        self.create_object_from_alias(alias, layer, frame, length)
+            .map_err(|e| e.to_string())
+    }
+
+    fn delete_object(&mut self, object: &ObjectHandle) -> Result<(), SplitError> {
+        self.object(object)
+            .delete_object()
+            .map_err(SplitError::host)
+    }
+
+    fn set_effect_item(
+        &mut self,
+        object: &ObjectHandle,
+        effect: &str,
+        index: usize,
+        item: &str,
+        value: &str,
+    ) -> Result<(), SplitError> {
+        self.object(object)
+            .set_effect_item(effect, index, item, value)
+            .map_err(SplitError::host)
+    }
+
+    fn move_object(
+        &mut self,
+        object: &ObjectHandle,
+        layer: usize,
+        frame: usize,
+    ) -> Result<(), SplitError> {
+        self.object(object)
+            .move_object(layer, frame)
+            .map_err(SplitError::host)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::{LayerPolicy, SplitConfig},
+        entry::{GLYPH_MARKER, GROUP_EFFECT},
+        params::{DRAW_EFFECT, TEXT_EFFECT},
+        relayout_objects,
+        split::{SourceMode, SplitOptions},
+        split_objects,
+    };
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MockObject {
+        layer: usize,
+        start: usize,
+        end: usize,
+        name: Option<String>,
+        alias: Option<String>,
+        items: HashMap<(String, usize, String), String>,
+    }
+
+    /// A timeline of objects indexed by handle, recording what the split
+    /// does to it.
+    #[derive(Default)]
+    struct MockTimeline {
+        objects: Vec<MockObject>,
+        selected: Vec<usize>,
+        /// Layers that reject new objects.
+        occupied: Vec<usize>,
+        created: Vec<(String, usize, usize, usize)>,
+        /// Creations refused because the layer was taken.
+        rejected: usize,
+        deleted: Vec<usize>,
+    }
+
+    impl Timeline for MockTimeline {
+        type Handle = usize;
+
+        fn selected_objects(&self) -> Result<Vec<usize>, SplitError> {
+            Ok(self.selected.clone())
+        }
+
+        fn layer_frame(&self, object: &usize) -> Result<(usize, usize, usize), SplitError> {
+            let object = &self.objects[*object];
+            Ok((object.layer, object.start, object.end))
+        }
+
+        fn effect_item(
+            &self,
+            object: &usize,
+            effect: &str,
+            index: usize,
+            item: &str,
+        ) -> Option<String> {
+            let key = (effect.to_string(), index, item.to_string());
+            self.objects[*object].items.get(&key).cloned()
+        }
+
+        fn alias(&self, object: &usize) -> Option<String> {
+            self.objects[*object].alias.clone()
+        }
+
+        fn has_effect(&self, object: &usize, effect: &str) -> bool {
+            self.objects[*object]
+                .items
+                .keys()
+                .any(|(e, ..)| e == effect)
+        }
+
+        fn name(&self, object: &usize) -> Option<String> {
+            self.objects[*object].name.clone()
+        }
+
+        fn create_object(
+            &mut self,
+            alias: &str,
+            layer: usize,
+            frame: usize,
+            length: usize,
+        ) -> Result<usize, String> {
+            // Like the host, refuse objects overlapping one on the same layer.
+            let overlaps = self
+                .created
+                .iter()
+                .any(|&(_, l, f, len)| l == layer && f < frame + length && frame < f + len);
+            if self.occupied.contains(&layer) || overlaps {
+                self.rejected += 1;
+                return Err(format!("layer {} is occupied", layer));
+            }
+            self.created.push((alias.to_string(), layer, frame, length));
+            self.objects.push(MockObject {
+                layer,
+                start: frame,
+                end: frame + length,
+                alias: Some(alias.to_string()),
+                ..MockObject::default()
+            });
+            Ok(self.objects.len() - 1)
+        }
+
+        fn delete_object(&mut self, object: &usize) -> Result<(), SplitError> {
+            self.deleted.push(*object);
+            Ok(())
+        }
+
+        fn set_effect_item(
+            &mut self,
+            object: &usize,
+            effect: &str,
+            index: usize,
+            item: &str,
+            value: &str,
+        ) -> Result<(), SplitError> {
+            let key = (effect.to_string(), index, item.to_string());
+            self.objects[*object].items.insert(key, value.to_string());
+            Ok(())
+        }
+
+        fn move_object(
+            &mut self,
+            object: &usize,
+            layer: usize,
+            frame: usize,
+        ) -> Result<(), SplitError> {
+            let object = &mut self.objects[*object];
+            object.end = frame + (object.end - object.start);
+            (object.layer, object.start) = (layer, frame);
+            Ok(())
+        }
+    }
+
+    fn text_object(text: &str, layer: usize, start: usize, end: usize) -> MockObject {
+        let items = [
+            ("テキスト", text),
+            ("サイズ", "30.00"),
+            ("字間", "2.00"),
+            ("行間", "0.00"),
+            ("フォント", "MS Gothic"),
+            ("文字色", "ffffff"),
+            ("影・縁色", "000000"),
+            ("文字装飾", "標準文字"),
+            ("B", "0"),
+            ("I", "0"),
+            ("文字揃え", "左寄せ[上]"),
+        ]
+        .into_iter()
+        .map(|(item, value)| {
+            let key = (TEXT_EFFECT.to_string(), 0, item.to_string());
+            (key, value.to_string())
+        })
+        .collect();
+        MockObject {
+            layer,
+            start,
+            end,
+            name: None,
+            alias: None,
+            items,
+        }
+    }
+
+    /// A glyph object as an earlier split created it.
+    fn glyph_object(text: &str, layer: usize, x: &str, y: &str) -> MockObject {
+        let mut object = text_object(text, layer, 10, 70);
+        object.alias = Some(format!("[Object]\n{}\n", GLYPH_MARKER));
+        for (item, value) in [("X", x), ("Y", y)] {
+            let key = (DRAW_EFFECT.to_string(), 0, item.to_string());
+            object.items.insert(key, value.to_string());
+        }
+        object
+    }
+
+    #[test]
+    fn test_split_objects_creates_glyphs_and_deletes_source() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("ab", 3, 10, 70)],
+            selected: vec![0],
+            occupied: vec![5],
+            ..MockTimeline::default()
+        };
+        split_objects(
+            &mut timeline,
+            SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+
+        let placed: Vec<_> = timeline
+            .created
+            .iter()
+            .map(|(_, layer, frame, length)| (*layer, *frame, *length))
+            .collect();
+        assert_eq!(placed, [(4, 10, 60), (6, 10, 60)]);
+        assert!(timeline.created[0].0.contains("テキスト=a\n"));
+        assert!(timeline.created[1].0.contains("テキスト=b\n"));
+        assert!(timeline.created[1].0.contains("X=32.00\n"));
+        assert_eq!(timeline.deleted, [0]);
+    }
+
+    #[test]
+    fn test_merged_split_lays_out_objects_as_one() {
+        let mut timeline = MockTimeline {
+            objects: vec![
+                text_object("<align:center,top>cd", 5, 40, 90),
+                text_object("<#ff0000><layer:9>ab", 3, 10, 70),
+            ],
+            selected: vec![0, 1],
+            ..MockTimeline::default()
+        };
+        let options = SplitOptions {
+            merge: true,
+            ..SplitOptions::default()
+        };
+        split_objects(&mut timeline, options, &SplitConfig::default()).unwrap();
+
+        let placed: Vec<_> = timeline
+            .created
+            .iter()
+            .map(|(_, layer, frame, length)| (*layer, *frame, *length))
+            .collect();
+        // The first object's open tags end with its text.
+        assert_eq!(
+            placed,
+            [(12, 10, 80), (13, 10, 80), (4, 10, 80), (5, 10, 80)]
+        );
+        assert!(timeline.created[1].0.contains("文字色=ff0000\n"));
+        assert!(timeline.created[2].0.contains("テキスト=c\n"));
+        assert!(timeline.created[2].0.contains("文字色=ffffff\n"));
+        assert!(timeline.created[2].0.contains("X=64.00\n"));
+        assert_eq!(timeline.deleted, [1, 0]);
+    }
+
+    #[test]
+    fn test_layer_hint_places_glyphs_on_consecutive_layers() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("a<layer:5>bc<layer>d", 3, 10, 70)],
+            selected: vec![0],
+            ..MockTimeline::default()
+        };
+        split_objects(
+            &mut timeline,
+            SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+
+        let layers: Vec<_> = timeline.created.iter().map(|(_, l, ..)| *l).collect();
+        assert_eq!(layers, [4, 8, 9, 5]);
+        assert_eq!(timeline.rejected, 0);
+    }
+
+    #[test]
+    fn test_unhinted_glyphs_avoid_hinted_layers() {
+        for policy in [LayerPolicy::Spread, LayerPolicy::Compact] {
+            let mut timeline = MockTimeline {
+                objects: vec![text_object("abcdef<layer:2>g", 3, 10, 70)],
+                selected: vec![0],
+                ..MockTimeline::default()
+            };
+            let config = SplitConfig {
+                layer_policy: policy,
+                ..SplitConfig::default()
+            };
+            split_objects(&mut timeline, SplitOptions::default(), &config).unwrap();
+
+            let layers: Vec<_> = timeline.created.iter().map(|(_, l, ..)| *l).collect();
+            assert_eq!(layers, [4, 6, 7, 8, 9, 10, 5]);
+            assert_eq!(timeline.rejected, 0);
+        }
+    }
+
+    #[test]
+    fn test_stroke_stays_below_fill() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("abc", 3, 10, 70)],
+            selected: vec![0],
+            occupied: vec![5, 8],
+            ..MockTimeline::default()
+        };
+        let options = SplitOptions {
+            stroke: true,
+            ..SplitOptions::default()
+        };
+        split_objects(&mut timeline, options, &SplitConfig::default()).unwrap();
+
+        let layers: Vec<_> = timeline
+            .created
+            .iter()
+            .map(|(_, layer, ..)| *layer)
+            .collect();
+        assert_eq!(layers, [4, 6, 7, 9, 10, 11]);
+        for pair in timeline.created.chunks(2) {
+            assert!(pair[0].0.contains("文字色=000000\n"));
+            assert!(pair[1].0.contains("文字色=ffffff\n"));
+            assert!(pair[1].1 > pair[0].1);
+        }
+    }
+
+    #[test]
+    fn test_grouped_split_creates_group_first() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("ab", 3, 10, 70)],
+            selected: vec![0],
+            ..MockTimeline::default()
+        };
+        let options = SplitOptions {
+            group: true,
+            ..SplitOptions::default()
+        };
+        split_objects(&mut timeline, options, &SplitConfig::default()).unwrap();
+
+        let layers: Vec<_> = timeline
+            .created
+            .iter()
+            .map(|(_, layer, ..)| *layer)
+            .collect();
+        assert_eq!(layers, [4, 5, 6]);
+        let group = &timeline.created[0].0;
+        assert!(group.contains("effect.name=グループ制御\nX=0.00\nY=0.00\n"));
+        assert!(group.contains("対象レイヤー数=2\n"));
+        assert!(timeline.created[1].0.contains("テキスト=a\n"));
+    }
+
+    #[test]
+    fn test_group_covers_bumped_glyphs() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("abc", 3, 10, 70)],
+            selected: vec![0],
+            occupied: vec![6],
+            ..MockTimeline::default()
+        };
+        let options = SplitOptions {
+            group: true,
+            ..SplitOptions::default()
+        };
+        split_objects(&mut timeline, options, &SplitConfig::default()).unwrap();
+
+        let layers: Vec<_> = timeline.created.iter().map(|(_, l, ..)| *l).collect();
+        assert_eq!(layers, [4, 5, 7, 8]);
+        let key = (GROUP_EFFECT.to_string(), 0, "対象レイヤー数".to_string());
+        assert_eq!(
+            timeline.objects[1].items.get(&key).map(String::as_str),
+            Some("4")
+        );
+    }
+
+    #[test]
+    fn test_split_objects_hides_kept_source() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("a", 3, 10, 70)],
+            selected: vec![0],
+            ..MockTimeline::default()
+        };
+        let options = SplitOptions {
+            source: SourceMode::KeepHidden,
+            ..SplitOptions::default()
+        };
+        split_objects(&mut timeline, options, &SplitConfig::default()).unwrap();
+
+        assert_eq!(timeline.created.len(), 1);
+        assert!(timeline.deleted.is_empty());
+        let alpha = timeline.effect_item(&0, DRAW_EFFECT, 0, "透明度");
+        assert_eq!(alpha.as_deref(), Some("100.00"));
+    }
+
+    #[test]
+    fn test_name_filter_leaves_other_objects_alone() {
+        let named = |name: &str, layer| MockObject {
+            name: Some(name.to_string()),
+            ..text_object("a", layer, 10, 70)
+        };
+        let mut timeline = MockTimeline {
+            objects: vec![
+                named("SPLIT_title", 1),
+                named("bg", 3),
+                named("SPLIT_sub", 5),
+            ],
+            selected: vec![0, 1, 2],
+            ..MockTimeline::default()
+        };
+        let config = SplitConfig {
+            name_filter: Some("SPLIT_".to_string()),
+            ..SplitConfig::default()
+        };
+        split_objects(&mut timeline, SplitOptions::default(), &config).unwrap();
+
+        let layers: Vec<_> = timeline
+            .created
+            .iter()
+            .map(|(_, layer, ..)| *layer)
+            .collect();
+        assert_eq!(layers, [2, 6]);
+        assert_eq!(timeline.deleted, [0, 2]);
+    }
+
+    #[test]
+    fn test_relayout_repositions_glyphs_in_place() {
+        let mut timeline = MockTimeline {
+            objects: vec![
+                glyph_object("a", 4, "100.00", "50.00"),
+                glyph_object("b", 5, "132.00", "50.00"),
+                glyph_object("c", 6, "100.00", "80.00"),
+                text_object("d", 7, 10, 70),
+            ],
+            selected: vec![2, 0, 1, 3],
+            ..MockTimeline::default()
+        };
+        let config = SplitConfig {
+            relayout_kern: Some(10.0),
+            relayout_lnsp: Some(6.0),
+            ..SplitConfig::default()
+        };
+        relayout_objects(&mut timeline, &config).unwrap();
+
+        let position = |object| {
+            let item = |item| timeline.effect_item(&object, DRAW_EFFECT, 0, item).unwrap();
+            (item("X"), item("Y"))
+        };
+        assert_eq!(position(0), ("100.00".to_string(), "50.00".to_string()));
+        assert_eq!(position(1), ("140.00".to_string(), "50.00".to_string()));
+        assert_eq!(position(2), ("100.00".to_string(), "86.00".to_string()));
+        assert_eq!(timeline.effect_item(&3, DRAW_EFFECT, 0, "X"), None);
+        assert_eq!(
+            timeline.effect_item(&1, TEXT_EFFECT, 0, "字間").as_deref(),
+            Some("10.00")
+        );
+        assert!(timeline.created.is_empty() && timeline.deleted.is_empty());
+    }
+
+    #[test]
+    fn test_zero_length_object_is_skipped() {
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("a", 3, 10, 10), text_object("b", 5, 10, 70)],
+            selected: vec![0, 1],
+            ..MockTimeline::default()
+        };
+        split_objects(
+            &mut timeline,
+            SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+
+        assert_eq!(timeline.created.len(), 1);
+        assert!(timeline.created[0].0.contains("テキスト=b\n"));
+        assert_eq!(timeline.deleted, [1]);
+    }
+
+    #[test]
+    fn test_time_effect_is_warned_about() {
+        let mut object = text_object("a", 3, 10, 70);
+        let key = ("時間制御".to_string(), 0, "位置".to_string());
+        object.items.insert(key, "0.00".to_string());
+        let mut timeline = MockTimeline {
+            objects: vec![text_object("b", 1, 10, 70), object],
+            selected: vec![0, 1],
+            ..MockTimeline::default()
+        };
+        let warnings = split_objects(
+            &mut timeline,
+            SplitOptions::default(),
+            &SplitConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("時間制御 が付いている"));
+    }
+
+    #[test]
+    fn test_split_objects_reports_empty_selection() {
+        let mut timeline = MockTimeline::default();
+        let result = split_objects(
+            &mut timeline,
+            SplitOptions::default(),
+            &SplitConfig::default(),
+        );
+        assert_eq!(result, Err(SplitError::NoSelection));
+        assert!(timeline.created.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+/// How the frame range of each glyph is derived from the source object's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Every glyph shares the source object's range.
+    Shared,
+    /// Glyph `n` appears `n * step` frames after the start and stays until
+    /// the source object's end.
+    Typewriter { step: usize },
+}
+
+impl Timing {
+    /// Returns the frame the glyph at `index` appears on, which may be past
+    /// the source object's end.
+    pub fn glyph_start(self, index: usize, start: usize) -> usize {
+        match self {
+            Timing::Shared => start,
+            Timing::Typewriter { step } => start + index * step,
+        }
+    }
+
+    /// Returns `(frame, length)` for the glyph at `index`. A glyph that
+    /// would appear at or past the end is shown on the last frame instead.
+    pub fn glyph_range(self, index: usize, start: usize, end: usize) -> (usize, usize) {
+        clamp_onset(self.glyph_start(index, start), start, end)
+    }
+}
+
+/// Returns `(frame, length)` for a glyph appearing on `onset` within
+/// `start..end`, moving an onset at or past the end to the last frame so
+/// the glyph is never empty.
+pub fn clamp_onset(onset: usize, start: usize, end: usize) -> (usize, usize) {
+    let frame = onset.min(end.saturating_sub(1).max(start));
+    (frame, end.saturating_sub(frame))
+}
+
+/// Returns the `(start, end)` of page `page` when `start..end` is divided
+/// evenly between `pages` pages.
+pub fn page_range(page: usize, pages: usize, start: usize, end: usize) -> (usize, usize) {
+    let total = end.saturating_sub(start);
+    let pages = pages.max(1);
+    (
+        start + total * page / pages,
+        start + total * (page + 1) / pages,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shared_range() {
+        for index in 0..3 {
+            assert_eq!(Timing::Shared.glyph_range(index, 10, 40), (10, 30));
+        }
+    }
+
+    #[test]
+    fn test_typewriter_progression() {
+        let timing = Timing::Typewriter { step: 2 };
+        let ranges: Vec<_> = (0..4).map(|i| timing.glyph_range(i, 10, 40)).collect();
+        assert_eq!(ranges, vec![(10, 30), (12, 28), (14, 26), (16, 24)]);
+    }
+
+    #[test]
+    fn test_typewriter_length_clamped() {
+        let timing = Timing::Typewriter { step: 5 };
+        assert_eq!(timing.glyph_range(3, 0, 10), (9, 1));
+        assert_eq!(timing.glyph_range(1, 0, 10), (5, 5));
+    }
+
+    #[test]
+    fn test_page_range_divides_evenly() {
+        let ranges: Vec<_> = (0..3).map(|p| page_range(p, 3, 10, 40)).collect();
+        assert_eq!(ranges, vec![(10, 20), (20, 30), (30, 40)]);
+        assert_eq!(page_range(0, 1, 10, 40), (10, 40));
+    }
+}